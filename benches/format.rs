@@ -0,0 +1,64 @@
+use std::{fmt::Write as _, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use user_spray::format;
+
+/// A file with thousands of imports, spread over many crates, modules and visibilities.
+fn synthetic_input() -> String {
+    let mut input = String::new();
+    for i in 0..4000 {
+        let vis = ["", "pub ", "pub(crate) "][i % 3];
+        let root = ["std", "core", "serde", "tokio", "crate", "self", "super"][i % 7];
+        writeln!(
+            input,
+            "{vis}use {root}::module_{}::submodule_{}::Item{i};",
+            i % 53,
+            i % 11
+        )
+        .unwrap();
+    }
+    input
+}
+
+/// A large file without any imports, e.g. a generated data module.
+fn import_free_input() -> String {
+    let mut input = String::from("pub static DATA: &[(u32, &str)] = &[\n");
+    for i in 0..20000 {
+        writeln!(input, "    ({i}, \"entry_{i}\"),").unwrap();
+    }
+    input.push_str("];\n");
+    input
+}
+
+fn bench_format(c: &mut Criterion) {
+    let input = synthetic_input();
+
+    c.bench_function("format large use block", |b| {
+        b.iter(|| {
+            let mut output = Vec::with_capacity(input.len());
+            format(black_box(&input), &mut output).unwrap();
+            output
+        })
+    });
+}
+
+fn bench_import_free(c: &mut Criterion) {
+    let input = import_free_input();
+    // a single import makes the whole file be parsed
+    let with_import = format!("use std::fmt;\n\n{input}");
+
+    let mut group = c.benchmark_group("format large file without imports");
+    for (name, input) in [("skipped", &input), ("parsed", &with_import)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut output = Vec::with_capacity(input.len());
+                format(black_box(input), &mut output).unwrap();
+                output
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_format, bench_import_free);
+criterion_main!(benches);
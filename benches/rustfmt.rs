@@ -0,0 +1,52 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Writes many small files with a few imports each, returning their paths.
+fn synthetic_files() -> Vec<PathBuf> {
+    let dir = env::temp_dir().join(format!("user-spray-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    (0..50)
+        .map(|i| {
+            let path = dir.join(format!("file_{i}.rs"));
+            fs::write(
+                &path,
+                format!(
+                    "use std::io;\nuse serde::Serialize;\nuse std::fmt::Display;\nuse crate::module_{i}::Item;\n\nfn main() {{}}\n"
+                ),
+            )
+            .unwrap();
+            path
+        })
+        .collect()
+}
+
+fn run(files: &[PathBuf], args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_user-spray"))
+        .args(args)
+        .args(files)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn bench_rustfmt(c: &mut Criterion) {
+    let files = synthetic_files();
+
+    let mut group = c.benchmark_group("rustfmt 50 files");
+    group.sample_size(10);
+    group.bench_function("once per file", |b| b.iter(|| run(&files, &[])));
+    group.bench_function("batched", |b| b.iter(|| run(&files, &["--batch-rustfmt"])));
+    group.finish();
+
+    fs::remove_dir_all(files[0].parent().unwrap()).unwrap();
+}
+
+criterion_group!(benches, bench_rustfmt);
+criterion_main!(benches);
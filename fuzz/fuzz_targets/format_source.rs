@@ -0,0 +1,18 @@
+//! Formats arbitrary text, asserting that formatting any valid file never panics and produces
+//! valid Rust.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use user_spray::{format_str, FormatOptions};
+
+fuzz_target!(|file: &str| {
+    // only valid files are formatted
+    let Ok(output) = format_str(file, &FormatOptions::default()) else {
+        return;
+    };
+
+    if let Err(err) = syn::parse_file(&output) {
+        panic!("output doesn't parse: {err}\n{output}");
+    }
+});
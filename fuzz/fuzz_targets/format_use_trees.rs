@@ -0,0 +1,141 @@
+//! Formats files made of generated `use` statements, asserting that formatting never panics and
+//! produces valid Rust.
+
+#![no_main]
+
+use std::fmt::{self, Display};
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use user_spray::{format_str, FormatError, FormatOptions, GroupImports};
+
+/// A small pool of idents, so that generated paths overlap and get merged.
+#[derive(Debug, Clone, Copy, Arbitrary)]
+enum Ident {
+    A,
+    B,
+    C,
+    Std,
+    Crate,
+    SelfValue,
+    Super,
+}
+
+impl Display for Ident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::A => "a",
+            Self::B => "b",
+            Self::C => "c",
+            Self::Std => "std",
+            Self::Crate => "crate",
+            Self::SelfValue => "self",
+            Self::Super => "super",
+        })
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum UseTree {
+    Path(Ident, Box<UseTree>),
+    Name(Ident),
+    Rename(Ident, Ident),
+    Glob,
+    Group(Vec<UseTree>),
+}
+
+impl Display for UseTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(ident, tree) => write!(f, "{ident}::{tree}"),
+            Self::Name(ident) => write!(f, "{ident}"),
+            Self::Rename(ident, rename) => write!(f, "{ident} as {rename}"),
+            Self::Glob => f.write_str("*"),
+            Self::Group(items) => {
+                f.write_str("{")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum Visibility {
+    Inherited,
+    Public,
+    Crate,
+}
+
+#[derive(Debug, Arbitrary)]
+struct ItemUse {
+    vis: Visibility,
+    leading_colon: bool,
+    cfg_test: bool,
+    tree: UseTree,
+}
+
+impl Display for ItemUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.cfg_test {
+            f.write_str("#[cfg(test)]\n")?;
+        }
+        f.write_str(match self.vis {
+            Visibility::Inherited => "",
+            Visibility::Public => "pub ",
+            Visibility::Crate => "pub(crate) ",
+        })?;
+        write!(
+            f,
+            "use {}{};",
+            if self.leading_colon { "::" } else { "" },
+            self.tree
+        )
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    items: Vec<ItemUse>,
+    group_imports_one: bool,
+    drop_glob_covered: bool,
+    max_width: u8,
+}
+
+fuzz_target!(|input: Input| {
+    let file = input
+        .items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    // the generator can produce invalid statements (e.g. `use *;`), which aren't interesting
+    if syn::parse_file(&file).is_err() {
+        return;
+    }
+
+    let opts = FormatOptions {
+        group_imports: if input.group_imports_one {
+            GroupImports::One
+        } else {
+            GroupImports::StdExternalCrate
+        },
+        drop_glob_covered: input.drop_glob_covered,
+        max_width: input.max_width.into(),
+        ..FormatOptions::default()
+    };
+    let output = match format_str(&file, &opts) {
+        // `use ::*;` parses, but is rejected
+        Err(FormatError::RootGlob { .. }) => return,
+        output => output.expect("input parses, so formatting can't fail"),
+    };
+
+    if let Err(err) = syn::parse_file(&output) {
+        panic!("output doesn't parse: {err}\n{file}\n---\n{output}");
+    }
+});
@@ -0,0 +1,92 @@
+use std::str::FromStr;
+
+use user_spray::{FormatOptions, ParseOptionsError};
+
+/// A single `--config KEY=VALUE` override of a [`FormatOptions`] field, mirroring rustfmt's flag
+/// of the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ConfigOverride {
+    key: String,
+    value: String,
+}
+
+impl ConfigOverride {
+    pub(super) fn apply(self, opts: &mut FormatOptions) {
+        opts.set(&self.key, &self.value)
+            .expect("overrides are validated when parsed");
+    }
+}
+
+impl FromStr for ConfigOverride {
+    type Err = ParseOptionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| ParseOptionsError::Syntax(s.to_owned()))?;
+        // whether a value is valid doesn't depend on the other options
+        FormatOptions::default().set(key, value)?;
+
+        Ok(Self {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use user_spray::{Category, GroupImports, Indent};
+
+    use super::*;
+
+    fn apply(config: &str) -> Result<FormatOptions, String> {
+        let mut opts = FormatOptions::default();
+        config
+            .parse::<ConfigOverride>()
+            .map_err(|err| err.to_string())?
+            .apply(&mut opts);
+        Ok(opts)
+    }
+
+    #[test]
+    fn test_parse_override() {
+        assert_eq!(
+            apply("group_imports=One").unwrap().group_imports,
+            GroupImports::One
+        );
+        assert_eq!(
+            apply("category_order = crate, std, external")
+                .unwrap()
+                .category_order,
+            [Category::Crate, Category::Std, Category::External]
+        );
+        assert_eq!(apply("indent=tabs").unwrap().indent, Indent::Tabs);
+
+        assert_eq!(
+            apply("blank_lines=0").unwrap_err(),
+            "unknown config key `blank_lines`"
+        );
+        assert_eq!(
+            apply("max_width=wide").unwrap_err(),
+            "invalid value `wide` for `max_width` (expected a number)"
+        );
+        assert_eq!(
+            apply("max_width").unwrap_err(),
+            "expected `KEY=VALUE`, got `max_width`"
+        );
+    }
+
+    #[test]
+    fn test_parse_category_order() {
+        for order in ["std,std,crate,external", "crate,std", "crate,std,extern"] {
+            assert_eq!(
+                apply(&format!("category_order={order}")).unwrap_err(),
+                format!(
+                    "invalid value `{order}` for `category_order` (expected a comma-separated \
+                     list of `std`, `external` and `crate`, each exactly once)"
+                )
+            );
+        }
+    }
+}
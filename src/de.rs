@@ -0,0 +1,83 @@
+//! `serde` support for [`FormatOptions`](crate::FormatOptions), e.g. for loading it from a config
+//! file.
+
+use std::fmt;
+
+use serde::{
+    de::{self, SeqAccess, Unexpected, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{Category, Indent};
+
+/// Categories are spelled the same as their [`Display`](fmt::Display) output.
+impl<'de> Deserialize<'de> for Category {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Indentation is either `"tabs"`, `"infer"`, or a number of spaces.
+impl<'de> Deserialize<'de> for Indent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IndentVisitor;
+
+        impl Visitor<'_> for IndentVisitor {
+            type Value = Indent;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("`\"tabs\"`, `\"infer\"` or a number of spaces")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                usize::try_from(v)
+                    .map(Indent::Spaces)
+                    .map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+                    .and_then(|v| self.visit_u64(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                match v {
+                    "tabs" => Ok(Indent::Tabs),
+                    "infer" => Ok(Indent::Infer),
+                    _ => Err(E::invalid_value(Unexpected::Str(v), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(IndentVisitor)
+    }
+}
+
+/// Deserializes [`FormatOptions::category_order`](crate::FormatOptions::category_order),
+/// rejecting lists that don't mention every category exactly once.
+pub(crate) fn category_order<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Category>, D::Error> {
+    struct CategoryOrderVisitor;
+
+    impl<'de> Visitor<'de> for CategoryOrderVisitor {
+        type Value = Vec<Category>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list of each category, in order")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut order = Vec::new();
+            while let Some(category) = seq.next_element::<Category>()? {
+                order.push(category);
+            }
+            Category::check_order(&order).map_err(de::Error::custom)?;
+            Ok(order)
+        }
+    }
+
+    deserializer.deserialize_seq(CategoryOrderVisitor)
+}
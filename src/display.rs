@@ -1,11 +1,14 @@
 use std::fmt::{self, Display};
 
 use fn_formats::DisplayFmt;
+use quote::ToTokens;
 use syn::{
-    Ident, ItemUse, Path, Token, UseGlob, UseGroup, UseName, UsePath, UseRename, UseTree,
-    VisRestricted, Visibility,
+    spanned::Spanned, Attribute, Ident, ItemUse, Path, Token, UseGlob, UseGroup, UseName, UsePath,
+    UseRename, UseTree, VisRestricted, Visibility,
 };
 
+use crate::{FormatOptions, Indent};
+
 pub(super) trait AsDisplay {
     fn as_display(&self) -> impl fmt::Display;
 }
@@ -126,9 +129,23 @@ impl AsDisplay for Visibility {
     }
 }
 
+impl AsDisplay for Attribute {
+    fn as_display(&self) -> impl fmt::Display {
+        // prefer the attribute's original text, which is only unavailable if it wasn't parsed
+        // from source (e.g. constructed by `parse_quote!`)
+        DisplayFmt(move |f| match self.span().source_text() {
+            Some(text) => f.write_str(&text),
+            None => write!(f, "{}", self.to_token_stream()),
+        })
+    }
+}
+
 impl AsDisplay for ItemUse {
     fn as_display(&self) -> impl fmt::Display {
         DisplayFmt(move |f| {
+            for attr in &self.attrs {
+                writeln!(f, "{}", attr.as_display())?;
+            }
             write!(
                 f,
                 "{}use {}{};",
@@ -139,3 +156,241 @@ impl AsDisplay for ItemUse {
         })
     }
 }
+
+/// The indentation of emitted statements: the indentation of the line they start at, plus levels
+/// of `unit` for the members of wrapped groups.
+#[derive(Debug, Clone)]
+pub(super) struct Indentation {
+    base: String,
+    unit: Indent,
+}
+
+impl Indentation {
+    pub(super) fn new(base: &str, unit: Indent) -> Self {
+        Self {
+            base: base.to_owned(),
+            unit,
+        }
+    }
+
+    /// The indentation of the line statements start at.
+    pub(super) fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// The text of the indentation `level` levels deeper than the base.
+    fn text(&self, level: usize) -> String {
+        format!("{}{}", self.base, self.unit.unit().repeat(level))
+    }
+
+    /// The width of the indentation `level` levels deeper than the base.
+    fn width(&self, level: usize) -> usize {
+        let base = self
+            .base
+            .chars()
+            .map(|c| if c == '\t' { Indent::Tabs.width() } else { 1 })
+            .sum::<usize>();
+        base + level * self.unit.width()
+    }
+}
+
+/// Renders `tree`, breaking groups over multiple lines (the way rustfmt does) if it doesn't fit
+/// in `max_width`, given that `used` columns of the line precede it and `suffix_len` columns
+/// follow it. `level` is the indentation level of the line `tree` starts at.
+fn wrap_tree(
+    tree: &UseTree,
+    opts: &FormatOptions,
+    indentation: &Indentation,
+    level: usize,
+    used: usize,
+    suffix_len: usize,
+) -> String {
+    let single_line = tree.as_display().to_string();
+    if used + single_line.len() + suffix_len <= opts.max_width {
+        return single_line;
+    }
+
+    match tree {
+        UseTree::Path(UsePath { ident, tree, .. }) => {
+            let ident = ident.to_string();
+            let used = used + ident.len() + "::".len();
+            format!(
+                "{ident}::{}",
+                wrap_tree(tree, opts, indentation, level, used, suffix_len)
+            )
+        }
+        UseTree::Group(UseGroup { items, .. }) => {
+            let mut wrapped = String::from("{\n");
+            for item in items {
+                let used = indentation.width(level + 1);
+                wrapped.push_str(&indentation.text(level + 1));
+                wrapped.push_str(&wrap_tree(
+                    item,
+                    opts,
+                    indentation,
+                    level + 1,
+                    used,
+                    ",".len(),
+                ));
+                wrapped.push_str(",\n");
+            }
+            wrapped.push_str(&indentation.text(level));
+            wrapped.push('}');
+            wrapped
+        }
+        // can't be broken up any further
+        UseTree::Name(_) | UseTree::Rename(_) | UseTree::Glob(_) => single_line,
+    }
+}
+
+/// Renders the attributes of `item` (each on a line of its own), followed by the rest of the
+/// statement preceding its tree, with the visibility padded to `vis_width` columns.
+fn prefix(item: &ItemUse, indentation: &Indentation, vis_width: usize) -> (String, String) {
+    let mut attrs = String::new();
+    for attr in &item.attrs {
+        attrs.push_str(&format!("{}\n", attr.as_display()));
+        attrs.push_str(indentation.base());
+    }
+
+    let vis = item.vis.as_display().to_string();
+    let prefix = format!("{vis:vis_width$}use {}", item.leading_colon.as_display());
+    (attrs, prefix)
+}
+
+/// Renders `item` on a single line (but for its attributes), with its visibility padded to
+/// `vis_width` columns.
+pub(super) fn single_line(item: &ItemUse, indentation: &Indentation, vis_width: usize) -> String {
+    let (attrs, prefix) = prefix(item, indentation, vis_width);
+    format!("{attrs}{prefix}{};", item.tree.as_display())
+}
+
+/// Renders `item`, breaking its groups over multiple lines if it doesn't fit in
+/// [`FormatOptions::max_width`], with its visibility padded to `vis_width` columns.
+pub(super) fn wrapped(
+    item: &ItemUse,
+    opts: &FormatOptions,
+    indentation: &Indentation,
+    vis_width: usize,
+) -> String {
+    let (mut rendered, prefix) = prefix(item, indentation, vis_width);
+    let used = indentation.width(0) + prefix.len();
+    rendered.push_str(&prefix);
+    rendered.push_str(&wrap_tree(
+        &item.tree,
+        opts,
+        indentation,
+        0,
+        used,
+        ";".len(),
+    ));
+    rendered.push(';');
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_snapshot;
+    use syn::parse_quote;
+
+    use super::*;
+
+    /// Renders `item`, checking that the result parses back into the same item.
+    fn render(item: ItemUse) -> String {
+        let rendered = item.as_display().to_string();
+        assert_eq!(syn::parse_str::<ItemUse>(&rendered).unwrap(), item);
+        rendered
+    }
+
+    #[test]
+    fn test_display_trees() {
+        assert_snapshot!(render(parse_quote!(use a;)), @"use a;");
+        assert_snapshot!(render(parse_quote!(use a::b as c;)), @"use a::b as c;");
+        assert_snapshot!(render(parse_quote!(use a::*;)), @"use a::*;");
+        assert_snapshot!(render(parse_quote!(use {a, b::c};)), @"use {a, b::c};");
+        assert_snapshot!(render(parse_quote!(use a::{};)), @"use a::{};");
+        assert_snapshot!(
+            render(parse_quote!(use a::{self, b::{c as _, d::{*}}, e};)),
+            @"use a::{self, b::{c as _, d::{*}}, e};"
+        );
+    }
+
+    #[test]
+    fn test_display_leading_colon() {
+        assert_snapshot!(render(parse_quote!(use ::a::b;)), @"use ::a::b;");
+        assert_snapshot!(render(parse_quote!(use ::{a, b};)), @"use ::{a, b};");
+    }
+
+    #[test]
+    fn test_display_visibility() {
+        assert_snapshot!(render(parse_quote!(pub use a;)), @"pub use a;");
+        assert_snapshot!(render(parse_quote!(pub(crate) use a;)), @"pub(crate) use a;");
+        assert_snapshot!(render(parse_quote!(pub(self) use a;)), @"pub(self) use a;");
+        assert_snapshot!(render(parse_quote!(pub(super) use a;)), @"pub(super) use a;");
+        assert_snapshot!(
+            render(parse_quote!(pub(in crate::a) use b;)),
+            @"pub(in crate::a) use b;"
+        );
+        assert_snapshot!(
+            render(parse_quote!(pub(in ::a::b) use c;)),
+            @"pub(in ::a::b) use c;"
+        );
+        assert_snapshot!(
+            render(parse_quote!(pub(in crate::a::b) use c::{d, e::f};)),
+            @"pub(in crate::a::b) use c::{d, e::f};"
+        );
+        assert_snapshot!(
+            render(parse_quote!(pub(in super::super::a) use ::b::*;)),
+            @"pub(in super::super::a) use ::b::*;"
+        );
+        // spacing in the source is normalized
+        assert_snapshot!(
+            syn::parse_str::<ItemUse>("pub( in crate :: a :: b ) use c ;")
+                .unwrap()
+                .as_display()
+                .to_string(),
+            @"pub(in crate::a::b) use c;"
+        );
+    }
+
+    #[test]
+    fn test_display_attributes() {
+        // attributes that weren't parsed from source are rendered from their tokens
+        assert_snapshot!(
+            render(parse_quote! {
+                #[cfg(test)]
+                #[allow(unused)]
+                pub(crate) use ::a::{b, c};
+            }),
+            @r###"
+        # [cfg (test)]
+        # [allow (unused)]
+        pub(crate) use ::a::{b, c};
+        "###
+        );
+    }
+
+    #[test]
+    fn test_display_constructed_tree() {
+        // trees built from nodes have default tokens (e.g. `Brace::default()`), not parsed ones
+        let tree = crate::tree::Tree::from_iter([
+            parse_quote!(a::b::c),
+            parse_quote!(a::b),
+            parse_quote!(a::d as e),
+            parse_quote!(a::f::*),
+        ]);
+        let items = tree
+            .into_use_trees()
+            .map(|tree| {
+                render(ItemUse {
+                    attrs: Vec::new(),
+                    vis: Visibility::Inherited,
+                    use_token: <Token![use]>::default(),
+                    leading_colon: None,
+                    tree,
+                    semi_token: <Token![;]>::default(),
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_snapshot!(items.join("\n"), @"use a::{b::{self, c}, d as e, f::{*}};");
+    }
+}
@@ -0,0 +1,8 @@
+use std::ops::Range;
+
+/// A replacement of a byte range of the input with new text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
@@ -0,0 +1,47 @@
+use std::{error::Error, fmt, io};
+
+/// An error encountered while formatting.
+#[derive(Debug)]
+pub enum FormatError {
+    /// The input couldn't be parsed as a Rust file.
+    Parse(syn::Error),
+    /// Writing the output failed.
+    Io(io::Error),
+    /// A `use` item (at the given line) glob-imports all crates (`use ::*;`), which isn't valid
+    /// Rust.
+    RootGlob { line: usize },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "failed to parse input: {err}"),
+            Self::Io(err) => write!(f, "failed to write output: {err}"),
+            Self::RootGlob { line } => {
+                write!(f, "line {line}: can't glob-import all crates")
+            }
+        }
+    }
+}
+
+impl Error for FormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::RootGlob { .. } => None,
+        }
+    }
+}
+
+impl From<syn::Error> for FormatError {
+    fn from(value: syn::Error) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl From<io::Error> for FormatError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
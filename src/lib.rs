@@ -1,82 +1,1078 @@
+#[cfg(feature = "serde")]
+mod de;
 mod display;
+mod edit;
+mod error;
 mod map;
+mod options;
+mod scope;
+mod shadow;
+mod stats;
 mod tree;
 
-use std::{error::Error, io::Write};
+use std::{borrow::Cow, collections::HashMap, io::Write, iter, ops::Range, slice};
 
-use syn::{spanned::Spanned, Item, ItemUse, Token, UseTree};
+use syn::{spanned::Spanned, Attribute, Item, ItemUse, Token, UseRename, UseTree, Visibility};
 
 use self::{
-    display::AsDisplay,
-    map::{Category, UseMap},
-    tree::Node,
+    display::{AsDisplay, Indentation},
+    map::{Entry, UseMap},
+    scope::Scope,
+    tree::Tree,
 };
+pub use self::{
+    edit::TextEdit,
+    error::FormatError,
+    map::{Category, ParseCategoryError},
+    map::{Name, UseKey},
+    options::{
+        CategoryMatcher, CategorySet, Classifier, Comparator, CrateOrder, FormatOptions,
+        GroupImports, Indent, ParseOptionsError, SortMode,
+    },
+    shadow::Shadowing,
+    stats::FormatStats,
+};
+
+/// Formats `file` with the default options, writing the result to `output`.
+///
+/// Returns whether the result differs from `file`.
+pub fn format(file: &str, output: impl Write) -> Result<bool, FormatError> {
+    format_with(file, output, &FormatOptions::default())
+}
+
+/// Formats `file`, writing the result to `output`.
+///
+/// Returns whether the result differs from `file`, so callers can e.g. avoid rewriting files
+/// that are already formatted.
+pub fn format_with(
+    file: &str,
+    mut output: impl Write,
+    opts: &FormatOptions,
+) -> Result<bool, FormatError> {
+    let edits = edits(file, opts)?;
+    let changed = !edits.is_empty();
+    output.write_all(apply_edits(file, edits).as_bytes())?;
+
+    Ok(changed)
+}
+
+/// Formats `file`, returning the result as a string.
+///
+/// This does no I/O and never spawns `rustfmt`, so it can be used in sandboxed environments
+/// such as `wasm32-unknown-unknown`.
+pub fn format_str(file: &str, opts: &FormatOptions) -> Result<String, FormatError> {
+    Ok(apply_edits(file, edits(file, opts)?))
+}
+
+fn apply_edits(file: &str, edits: Vec<TextEdit>) -> String {
+    let mut output = String::with_capacity(file.len());
+    let mut last_end = 0;
+
+    for TextEdit { range, replacement } in edits {
+        output.push_str(&file[last_end..range.start]);
+        output.push_str(&replacement);
+        last_end = range.end;
+    }
+
+    output.push_str(&file[last_end..]);
+
+    output
+}
+
+/// Returns the edits [`format_with`] would apply to `file`, one per changed run of `use` items.
+///
+/// Unchanged runs produce no edit, so an empty result means `file` is already formatted. Files
+/// without the `use` keyword have nothing to format, so they aren't even parsed (and never fail
+/// with [`FormatError::Parse`]).
+pub fn edits(file: &str, opts: &FormatOptions) -> Result<Vec<TextEdit>, FormatError> {
+    collect_edits(file, 0..file.len(), opts, &mut FormatStats::default())
+}
+
+/// Counts the `use` statements of `file` before and after formatting.
+pub fn stats(file: &str, opts: &FormatOptions) -> Result<FormatStats, FormatError> {
+    Ok(edits_with_stats(file, opts)?.1)
+}
+
+/// Like [`edits`], also returning the [`stats`] of `file`, gathered in the same pass.
+pub fn edits_with_stats(
+    file: &str,
+    opts: &FormatOptions,
+) -> Result<(Vec<TextEdit>, FormatStats), FormatError> {
+    let mut stats = FormatStats::default();
+    let edits = collect_edits(file, 0..file.len(), opts, &mut stats)?;
+    Ok((edits, stats))
+}
+
+/// Like [`format_str`], also returning the [`stats`] of `file`, gathered in the same pass.
+pub fn format_str_with_stats(
+    file: &str,
+    opts: &FormatOptions,
+) -> Result<(String, FormatStats), FormatError> {
+    let (edits, stats) = edits_with_stats(file, opts)?;
+    Ok((apply_edits(file, edits), stats))
+}
+
+/// Formats the runs of `use` items of `file` that are entirely within `byte_range`, leaving the
+/// rest of it as it is, e.g. to format an editor's selection.
+///
+/// Runs only partially within the range are skipped, rather than formatted beyond it. So is the
+/// spacing around the formatted runs when it's outside the range (e.g. the blank lines between
+/// the last run and the code after it, see
+/// [`normalize_trailing_blank_lines`](FormatOptions::normalize_trailing_blank_lines)).
+pub fn format_range(
+    file: &str,
+    byte_range: Range<usize>,
+    opts: &FormatOptions,
+) -> Result<String, FormatError> {
+    let edits = collect_edits(file, byte_range, opts, &mut FormatStats::default())?;
+    Ok(apply_edits(file, edits))
+}
+
+/// Collects the edits formatting the runs of `use` items of `file` within `selection`.
+fn collect_edits(
+    file: &str,
+    selection: Range<usize>,
+    opts: &FormatOptions,
+    stats: &mut FormatStats,
+) -> Result<Vec<TextEdit>, FormatError> {
+    let selected =
+        |range: &Range<usize>| selection.start <= range.start && range.end <= selection.end;
+
+    if !may_have_use_items(file) {
+        return Ok(Vec::new());
+    }
 
-pub fn format(file: &str, mut output: impl Write) -> Result<(), Box<dyn Error>> {
     let parsed_file = syn::parse_file(file)?;
+    let opts = &with_std_aliases(&parsed_file.items, opts);
+    let mut block_runs = if opts.sort_block_imports {
+        block_runs(&parsed_file.items)
+    } else {
+        Vec::new()
+    };
+    if opts.sort_module_imports {
+        block_runs.extend(module_runs(&parsed_file.items));
+    }
+
+    // whether the first run is preceded by nothing but the file's docs and inner attributes
+    let first_item = matches!(parsed_file.items.first(), Some(Item::Use(_)));
+    let runs = use_runs(parsed_file.items.into_iter().map(|item| match item {
+        Item::Use(item) => Some(item),
+        _ => None,
+    }));
+    let last_run = runs.len().saturating_sub(1);
+
+    let mut edits = Vec::new();
+    for (i, (range, items)) in runs.into_iter().enumerate() {
+        if is_skipped(file, &range, &items, opts) {
+            continue;
+        }
+        let range = with_header(file, range, &items, opts);
+        if !selected(&range) {
+            continue;
+        }
+        check_roots(&items)?;
+
+        let replacement = format_run(file, items, opts, stats);
+        if replacement.is_empty() {
+            edits.push(removal(file, range, &selection));
+            continue;
+        }
+        let mut edit = TextEdit { range, replacement };
+
+        // normalize the spacing between the file's docs and the first run, if set
+        if let Some(blank_lines) = opts
+            .blank_after_module_docs
+            .filter(|_| i == 0 && first_item)
+        {
+            let head = file[..edit.range.start].trim_end();
+            let separator = "\n".repeat(blank_lines + 1);
+            if !head.is_empty()
+                && file[head.len()..edit.range.start] != *separator
+                && head.len() >= selection.start
+            {
+                edit.range.start = head.len();
+                edit.replacement.insert_str(0, &separator);
+            }
+        }
+
+        // normalize the spacing between the last run and any code following it to a single blank
+        // line (if set), or, if the run ends the file, to exactly one trailing newline
+        if i == last_run {
+            let tail = file[edit.range.end..].trim_start();
+            let separator = if tail.is_empty() { "\n" } else { "\n\n" };
+            // keep edits minimal when the spacing is already right
+            if file[edit.range.end..file.len() - tail.len()] != *separator
+                && (tail.is_empty() || opts.normalize_trailing_blank_lines)
+                && file.len() - tail.len() <= selection.end
+            {
+                edit.range.end = file.len() - tail.len();
+                edit.replacement.push_str(separator);
+            }
+        }
+
+        edits.push(edit);
+    }
 
-    let mut items = parsed_file.items.into_iter().peekable();
-    let mut last_use_span = None;
+    let block_runs = block_runs
+        .into_iter()
+        .filter(|(range, items)| !is_skipped(file, range, items, opts))
+        .map(|(range, items)| (with_header(file, range, &items, opts), items))
+        .filter(|(range, _)| selected(range))
+        .collect::<Vec<_>>();
+    for (_, items) in &block_runs {
+        check_roots(items)?;
+    }
+    edits.extend(block_runs.into_iter().map(|(range, items)| {
+        match format_run(file, items, opts, stats) {
+            replacement if replacement.is_empty() => removal(file, range, &selection),
+            replacement => TextEdit { range, replacement },
+        }
+    }));
+    edits.sort_by_key(|edit| edit.range.start);
+
+    Ok(edits
+        .into_iter()
+        .filter(|edit| !is_unchanged(&file[edit.range.clone()], &edit.replacement, opts))
+        .collect())
+}
+
+/// Whether `file` may have `use` items, i.e. it has the `use` keyword anywhere (even in a comment
+/// or a string). Files without it are left as they are without parsing them, which is costly for
+/// large (e.g. generated) files.
+fn may_have_use_items(file: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    file.match_indices("use").any(|(i, _)| {
+        // `r#use` is an ident, not the keyword
+        !file[..i].ends_with(|c| is_ident(c) || c == '#') && !file[i + 3..].starts_with(is_ident)
+    })
+}
+
+/// Lists the paths imported by the `use` items of `file` (at the top level, in inline modules and
+/// in the bodies of functions, at any depth), e.g. for dependency analysis. Each import is
+/// flattened into its full path, in the order they appear: `use ::a::{self, b as c, d::*};`
+/// imports `::a::self`, `::a::b as c` and `::a::d::*`.
+pub fn imported_paths(file: &str) -> Result<Vec<String>, FormatError> {
+    let parsed_file = syn::parse_file(file)?;
+
+    let mut items = iter::once(Scope::Module(&parsed_file.items))
+        .chain(scope::nested_scopes(&parsed_file.items))
+        .flat_map(Scope::use_items)
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    items.sort_by_key(|item| item.span().byte_range().start);
+
+    Ok(items
+        .into_iter()
+        .flat_map(|item| {
+            let leading_colon = if item.leading_colon.is_some() {
+                "::"
+            } else {
+                ""
+            };
+            tree::paths(item.tree)
+                .into_iter()
+                .map(move |path| format!("{leading_colon}{path}"))
+        })
+        .collect())
+}
+
+/// Finds imports binding the same name as an earlier import in the same scope (a module or a
+/// function body), but to a different path, e.g. `use b::Error;` after `use a::Error;`. Rust
+/// rejects these, unless they're gated by `#[cfg]` attributes (which is why imports with any are
+/// ignored), so they're likely a mistake. Globs and `_` renames don't bind names.
+pub fn shadowed_imports(file: &str) -> Result<Vec<Shadowing>, FormatError> {
+    let parsed_file = syn::parse_file(file)?;
+    Ok(shadow::find(&parsed_file.items))
+}
+
+/// Adds the aliases that top-level `use` items give std-like crates (e.g. `rust_std` in
+/// `use std as rust_std;`) to [`FormatOptions::std_crates`], so that imports through them are
+/// categorized along with the crates they alias.
+fn with_std_aliases<'a>(items: &[Item], opts: &'a FormatOptions) -> Cow<'a, FormatOptions> {
+    let aliases = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Use(item) => Some(item),
+            _ => None,
+        })
+        .flat_map(|item| {
+            let leading_colon = item.leading_colon.map(|_| "");
+            tree::split_root_group(item.tree.clone())
+                .into_iter()
+                .filter_map(move |tree| match tree {
+                    UseTree::Rename(UseRename { ident, rename, .. }) if rename != "_" => {
+                        let ident = ident.to_string();
+                        let path = leading_colon.into_iter().chain([ident.as_str()]);
+                        let category = Category::of_path(&path.collect::<Vec<_>>(), opts);
+                        (category == Category::Std).then(|| rename.to_string())
+                    }
+                    _ => None,
+                })
+        })
+        .filter(|alias| !opts.std_crates.contains(alias))
+        .collect::<Vec<_>>();
+
+    if aliases.is_empty() {
+        return Cow::Borrowed(opts);
+    }
+    let mut opts = opts.clone();
+    opts.std_crates.extend(aliases);
+    Cow::Owned(opts)
+}
+
+/// Removes a run that imports nothing (e.g. `use foo::{};`), along with the lines it occupies and
+/// any blank lines following them, so that it leaves no gap behind. Whitespace outside of
+/// `selection` is kept, though (see [`format_range`]).
+fn removal(file: &str, range: Range<usize>, selection: &Range<usize>) -> TextEdit {
+    let line_start = file[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let start = if file[line_start..range.start].trim().is_empty() && line_start >= selection.start
+    {
+        line_start
+    } else {
+        range.start
+    };
+
+    let mut end = range.end;
+    for line in file[range.end..].split_inclusive('\n') {
+        if !line.trim().is_empty() || end + line.len() > selection.end {
+            break;
+        }
+        end += line.len();
+    }
+
+    let head = file[..start].trim_end().len();
+    if end < file.len() || head < selection.start {
+        return TextEdit {
+            range: start..end,
+            replacement: String::new(),
+        };
+    }
+
+    // nothing follows the run, so the file ends with whatever precedes it
+    TextEdit {
+        range: head..end,
+        replacement: if head == 0 { "" } else { "\n" }.to_owned(),
+    }
+}
+
+/// Checks that the roots of the trees of `items` can be categorized. Paths, names and renames are
+/// categorized by their first ident, root globs (`use *;`, importing the crate root's items) as
+/// crate-local, and the members of root groups each on their own (so empty groups, importing
+/// nothing, are dropped). Only globs following a leading `::` (`use ::*;`) are rejected.
+fn check_roots(items: &[ItemUse]) -> Result<(), FormatError> {
+    for item in items {
+        if item.leading_colon.is_some()
+            && tree::split_root_group(item.tree.clone())
+                .iter()
+                .any(|tree| matches!(tree, UseTree::Glob(_)))
+        {
+            return Err(FormatError::RootGlob {
+                line: item.span().start().line,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Extends a run's range to include the header comment above it, if
+/// [`FormatOptions::category_headers`] is set, since it is regenerated along with the run.
+fn with_header(
+    file: &str,
+    range: Range<usize>,
+    items: &[ItemUse],
+    opts: &FormatOptions,
+) -> Range<usize> {
+    match block_header(file, items, 0) {
+        Some((start, _)) if opts.keeps_category_headers() => start..range.end,
+        _ => range,
+    }
+}
+
+/// Whether a run should be left untouched: either an item in it is marked `#[rustfmt::skip]`, or
+/// the run is preceded by a `// user-spray:skip` comment.
+///
+/// With [`FormatOptions::always_single_line`], `#[rustfmt::skip]` is also what marks long
+/// statements, so it only causes runs to be skipped when it marks an item that would fit without
+/// it.
+fn is_skipped(file: &str, range: &Range<usize>, items: &[ItemUse], opts: &FormatOptions) -> bool {
+    let skip_comment = file[..range.start]
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        == Some("// user-spray:skip");
+    let indentation = run_indentation(file, items, opts);
+    let skip_attr = items.iter().any(|item| {
+        item.attrs.iter().any(is_rustfmt_skip)
+            && !(opts.always_single_line && is_marked_too_wide(item, &indentation, opts))
+    });
+
+    skip_comment || skip_attr
+}
+
+/// Whether `item` is too wide to fit on a single line, so that its `#[rustfmt::skip]` may be the
+/// marker added by [`FormatOptions::always_single_line`].
+fn is_marked_too_wide(item: &ItemUse, indentation: &Indentation, opts: &FormatOptions) -> bool {
+    let mut item = item.clone();
+    item.attrs.retain(|attr| !is_rustfmt_skip(attr));
+    is_too_wide(&display::single_line(&item, indentation, 0), opts)
+}
+
+/// Whether a statement rendered on a single line doesn't fit in [`FormatOptions::max_width`].
+fn is_too_wide(statement: &str, opts: &FormatOptions) -> bool {
+    statement.lines().last().unwrap().len() > opts.max_width
+}
+
+fn is_rustfmt_skip(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .eq(["rustfmt", "skip"])
+}
+
+fn is_unchanged(original: &str, replacement: &str, opts: &FormatOptions) -> bool {
+    if original == replacement {
+        return true;
+    }
+
+    // both texts are known to parse, since the original was already parsed once and the
+    // replacement is generated. rendering them normalizes whitespace and trailing commas.
+    let rendered = |text: &str| {
+        syn::parse_file(text)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Use(item) => item.as_display().to_string(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+    opts.preserve_unchanged && rendered(original) == rendered(replacement)
+}
+
+/// Splits items (`None` standing for anything that isn't a `use` item) into contiguous runs of
+/// `use` items, along with the byte range each run spans.
+fn use_runs(items: impl IntoIterator<Item = Option<ItemUse>>) -> Vec<(Range<usize>, Vec<ItemUse>)> {
+    let mut items = items.into_iter().peekable();
+    let mut runs = Vec::new();
 
     loop {
-        while items
-            .next_if(|item| !matches!(item, Item::Use(_)))
-            .is_some()
-        {}
+        while items.next_if(Option::is_none).is_some() {}
 
         if items.peek().is_none() {
             break;
         }
 
-        let items = items
-            .by_ref()
-            .map_while(|item| match item {
-                Item::Use(item) => Some(item),
-                _ => None,
+        let items = items.by_ref().map_while(|item| item).collect::<Vec<_>>();
+        // an item's span includes its outer attributes (but not the file's inner ones), so the
+        // run's attributes are replaced along with it and never duplicated
+        let (first, last) = (items[0].span(), items[items.len() - 1].span());
+        // joining fails if the spans don't come from the same source (e.g. they were parsed
+        // separately), in which case the byte ranges are the best we have
+        let range = first.join(last).map_or_else(
+            || first.byte_range().start..last.byte_range().end,
+            |span| span.byte_range(),
+        );
+
+        runs.push((range, items));
+    }
+
+    runs
+}
+
+/// Finds runs of `use` items in the bodies of functions, at any depth (see
+/// [`scope::nested_scopes`]).
+fn block_runs(items: &[Item]) -> Vec<(Range<usize>, Vec<ItemUse>)> {
+    nested_runs(items, |scope| matches!(scope, Scope::Body(_)))
+}
+
+/// Finds runs of `use` items in inline modules (`mod foo { ... }`), at any depth (see
+/// [`scope::nested_scopes`]).
+fn module_runs(items: &[Item]) -> Vec<(Range<usize>, Vec<ItemUse>)> {
+    nested_runs(items, |scope| matches!(scope, Scope::Module(_)))
+}
+
+fn nested_runs(
+    items: &[Item],
+    filter: impl Fn(&Scope) -> bool,
+) -> Vec<(Range<usize>, Vec<ItemUse>)> {
+    scope::nested_scopes(items)
+        .into_iter()
+        .filter(filter)
+        .flat_map(|scope| use_runs(scope.use_items().into_iter().map(|item| item.cloned())))
+        .collect()
+}
+
+/// Groups, sorts and merges a run of `use` items, returning the resulting import text.
+///
+/// Each group of imports (by default, each category) is emitted as a block of statements, with a
+/// single blank line between non-empty blocks. The text ends right after the last statement (with
+/// no trailing newline), so it can replace the original run's text as-is.
+pub fn format_items(items: Vec<ItemUse>, opts: &FormatOptions) -> String {
+    format_items_at(
+        items.into_iter().map(|item| (item, Vec::new())),
+        HashMap::new(),
+        opts,
+        &Indentation::new("", opts.indent.resolve("")),
+        &mut FormatStats::default(),
+    )
+}
+
+/// Formats `use` items added one at a time, e.g. by an editor as imports are added to a file,
+/// rendering the canonical block of statements for the items added so far on demand.
+///
+/// Items are merged as they're added, so rendering doesn't go over all of them again.
+///
+/// ```
+/// use user_spray::{FormatOptions, Formatter};
+///
+/// let opts = FormatOptions::default();
+/// let mut formatter = Formatter::new(&opts);
+/// formatter.push(syn::parse_quote!(use std::fmt;));
+/// assert_eq!(formatter.render(), "use std::{fmt};");
+/// formatter.push(syn::parse_quote!(use std::io;));
+/// assert_eq!(formatter.render(), "use std::{fmt, io};");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Formatter<'a> {
+    opts: &'a FormatOptions,
+    use_map: UseMap<'a>,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(opts: &'a FormatOptions) -> Self {
+        Self {
+            opts,
+            use_map: UseMap::new(opts),
+        }
+    }
+
+    pub fn push(&mut self, mut item: ItemUse) {
+        unmark(&mut item, self.opts);
+        self.use_map.extend([(item, Vec::new())]);
+    }
+
+    /// Renders the items added so far, like [`format_items`] would.
+    pub fn render(&self) -> String {
+        render_map(
+            self.use_map.clone(),
+            HashMap::new(),
+            self.opts,
+            &Indentation::new("", self.opts.indent.resolve("")),
+            &mut FormatStats::default(),
+        )
+    }
+}
+
+impl Extend<ItemUse> for Formatter<'_> {
+    fn extend<T: IntoIterator<Item = ItemUse>>(&mut self, iter: T) {
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+/// Formats a single `use` statement (e.g. for an editor action normalizing one import), returning
+/// its normalized text with no trailing newline.
+///
+/// Fails unless `use_stmt` is exactly one `use` item (surrounding whitespace aside). The result
+/// may be several statements (e.g. `use {std::fmt, crate::a};` is split by category), or none for
+/// a statement importing nothing.
+pub fn format_one(use_stmt: &str, opts: &FormatOptions) -> Result<String, FormatError> {
+    let item = syn::parse_str::<ItemUse>(use_stmt)?;
+    check_roots(slice::from_ref(&item))?;
+    Ok(format_items(vec![item], opts))
+}
+
+/// The indentation of the statements a run of `use` items parsed from `file` is formatted into.
+fn run_indentation(file: &str, items: &[ItemUse], opts: &FormatOptions) -> Indentation {
+    // the run starts after the indentation of its first line, which is reused for the lines
+    // following it
+    let start = items[0].span().byte_range().start;
+    let line = &file[file[..start].rfind('\n').map_or(0, |i| i + 1)..start];
+    let base = if line.trim().is_empty() { line } else { "" };
+    Indentation::new(base, opts.indent.resolve(file))
+}
+
+/// Formats a run of `use` items parsed from `file`, keeping the comments between them if
+/// [`FormatOptions::preserve_comments`] is set.
+fn format_run(
+    file: &str,
+    items: Vec<ItemUse>,
+    opts: &FormatOptions,
+    stats: &mut FormatStats,
+) -> String {
+    let indentation = run_indentation(file, &items, opts);
+
+    let mut comments = if opts.preserve_comments {
+        interior_comments(file, &items)
+    } else {
+        vec![Vec::new(); items.len()]
+    };
+
+    let mut headers = HashMap::<_, Vec<_>>::new();
+    if opts.keeps_category_headers() {
+        for (i, item) in items.iter().enumerate() {
+            let Some((_, header)) = block_header(file, &items, i) else {
+                continue;
+            };
+            if comments[i].last() == Some(&header) {
+                comments[i].pop();
+            }
+
+            let headers = headers.entry(Category::of_item(item, opts)).or_default();
+            if !headers.contains(&header) {
+                headers.push(header);
+            }
+        }
+    }
+
+    if opts.group_imports != GroupImports::Preserve {
+        return format_items_at(
+            items.into_iter().zip(comments),
+            headers,
+            opts,
+            &indentation,
+            stats,
+        );
+    }
+
+    // each block of the original is formatted on its own
+    let separated = iter::once(true)
+        .chain(
+            items
+                .windows(2)
+                .map(|pair| is_blank_separated(file, &pair[0], &pair[1])),
+        )
+        .collect::<Vec<_>>();
+    let mut blocks = Vec::<Vec<_>>::new();
+    for (item, separated) in items.into_iter().zip(comments).zip(separated) {
+        if separated {
+            blocks.push(Vec::new());
+        }
+        blocks.last_mut().unwrap().push(item);
+    }
+    blocks
+        .into_iter()
+        .map(|items| format_items_at(items, HashMap::new(), opts, &indentation, stats))
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join(&format!("\n\n{}", indentation.base()))
+}
+
+/// Whether a blank line separates two consecutive items of a run (comments between them aside).
+fn is_blank_separated(file: &str, item: &ItemUse, next: &ItemUse) -> bool {
+    let gap = &file[item.span().byte_range().end..next.span().byte_range().start];
+    // the first line is the rest of the first item's line, and the last is the indentation of the
+    // second item's
+    let lines = gap.split('\n').collect::<Vec<_>>();
+    lines.len() > 2
+        && lines[1..lines.len() - 1]
+            .iter()
+            .any(|line| line.trim().is_empty())
+}
+
+/// Finds the header of the block starting at item `i` of a run: a single-line `//` comment right
+/// above it, following a blank line (or, for the run's first item, any line that isn't a comment
+/// itself). Returns the comment's offset in `file` along with its text.
+fn block_header(file: &str, items: &[ItemUse], i: usize) -> Option<(usize, String)> {
+    let is_comment = |line: &str| line.starts_with("//");
+
+    let before = &file[..items[i].span().byte_range().start];
+    let line_end = before.rfind('\n')?;
+    let line_start = before[..line_end].rfind('\n').map_or(0, |i| i + 1);
+    let line = file[line_start..line_end].trim();
+    if !is_comment(line) || line.starts_with("///") || line.starts_with("//!") {
+        return None;
+    }
+
+    let previous_line = line_start
+        .checked_sub(1)
+        .map(|end| (before[..end].rfind('\n').map_or(0, |i| i + 1), end));
+    let is_header = match (i, previous_line) {
+        (0, None) => true,
+        (0, Some((start, end))) => !is_comment(file[start..end].trim()),
+        (_, Some((start, end))) => {
+            start > items[i - 1].span().byte_range().end && file[start..end].trim().is_empty()
+        }
+        (_, None) => false,
+    };
+
+    is_header.then(|| {
+        let offset = file[line_start..].len() - file[line_start..].trim_start().len();
+        (line_start + offset, line.to_owned())
+    })
+}
+
+/// Finds the comments between consecutive items of a run, pairing each item with the comments
+/// on the lines preceding it. A comment trailing an item on the same line is paired with that
+/// item instead.
+///
+/// Each line of a comment is kept on its own. The continuation lines of block comments keep their
+/// indentation relative to the line the comment starts on; other lines are trimmed.
+fn interior_comments(file: &str, items: &[ItemUse]) -> Vec<Vec<String>> {
+    let mut comments = vec![Vec::new(); items.len()];
+
+    for (i, pair) in items.windows(2).enumerate() {
+        let start = pair[0].span().byte_range().end;
+        let gap = &file[start..pair[1].span().byte_range().start];
+
+        // the indentation of the line the current block comment started on, and how many block
+        // comments are open (they nest)
+        let line_start = file[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = &file[line_start..start];
+        let mut indent = &line[..line.len() - line.trim_start().len()];
+        let mut depth = 0_usize;
+        // whether the comments are still trailing the first item: up to the end of its line, or
+        // of a block comment starting on it
+        let mut trailing = true;
+
+        for (n, line) in gap.lines().enumerate() {
+            trailing &= n == 0 || depth > 0;
+            let comments = &mut comments[if trailing { i } else { i + 1 }];
+            let content = line.trim();
+
+            if depth > 0 {
+                comments.push(
+                    line.strip_prefix(indent)
+                        .unwrap_or(content)
+                        .trim_end()
+                        .to_owned(),
+                );
+            } else if !content.is_empty() {
+                comments.push(content.to_owned());
+                if n > 0 {
+                    indent = &line[..line.len() - line.trim_start().len()];
+                }
+            }
+            if depth > 0 || content.starts_with("/*") {
+                depth = (depth + content.matches("/*").count())
+                    .saturating_sub(content.matches("*/").count());
+            }
+        }
+    }
+
+    comments
+}
+
+/// Like [`format_items`], for items each paired with its comments, and with header comments to
+/// emit above the blocks of categories. Every line but the first is indented, since the first
+/// replaces text that already is.
+fn format_items_at(
+    items: impl IntoIterator<Item = (ItemUse, Vec<String>)>,
+    headers: HashMap<Category, Vec<String>>,
+    opts: &FormatOptions,
+    indentation: &Indentation,
+    stats: &mut FormatStats,
+) -> String {
+    let mut items = items.into_iter().collect::<Vec<_>>();
+    stats.parsed += items.len();
+
+    for (item, _) in &mut items {
+        unmark(item, opts);
+    }
+
+    let mut use_map = UseMap::new(opts);
+    use_map.extend(items);
+    render_map(use_map, headers, opts, indentation, stats)
+}
+
+/// Removes the `#[rustfmt::skip]` marking a long statement with
+/// [`FormatOptions::always_single_line`], since it's (re-)marked when it's rendered (and any other
+/// marked statement would have caused its run to be skipped).
+fn unmark(item: &mut ItemUse, opts: &FormatOptions) {
+    if opts.always_single_line {
+        item.attrs.retain(|attr| !is_rustfmt_skip(attr));
+    }
+}
+
+/// Renders the blocks of statements of the items in `use_map`, with header comments to emit above
+/// the blocks of categories.
+fn render_map(
+    mut use_map: UseMap,
+    mut headers: HashMap<Category, Vec<String>>,
+    opts: &FormatOptions,
+    indentation: &Indentation,
+    stats: &mut FormatStats,
+) -> String {
+    let indent = indentation.base();
+    // each group is paired with its header (only the first group of a category has one), and
+    // with whether it's separated from the previous group by a blank line
+    let groups: Vec<(Vec<String>, bool, _)> = match opts.group_imports {
+        GroupImports::StdExternalCrate => {
+            let order = opts.category_order().collect::<Vec<_>>();
+            // custom categories missing from the order go last
+            let missing = use_map
+                .custom_categories()
+                .into_iter()
+                .filter(|category| !order.contains(category))
+                .collect::<Vec<_>>();
+            order
+                .into_iter()
+                .chain(missing)
+                .flat_map(|category| {
+                    let entries = use_map.take(category);
+                    let groups = if category == Category::External && opts.split_external_crates {
+                        split_by_crate(entries)
+                    } else {
+                        vec![entries]
+                    };
+                    let header = headers.remove(&category).unwrap_or_default();
+                    iter::once((header, opts.blank_line_before(category)))
+                        .chain(iter::repeat((Vec::new(), true)))
+                        .zip(groups)
+                        .map(|((header, blank_line), entries)| (header, blank_line, entries))
+                })
+                .collect()
+        }
+        // `Preserve` blocks are split up before they get here
+        GroupImports::One | GroupImports::Preserve => {
+            vec![(Vec::new(), true, use_map.take_all())]
+        }
+    };
+    let groups = if opts.separate_pub_use {
+        groups
+            .into_iter()
+            .flat_map(|(header, blank_line, entries)| {
+                // re-exports go in a block of their own after the rest, keeping the header above
+                // the first non-empty one
+                let (public, rest): (Vec<_>, Vec<_>) = entries
+                    .into_iter()
+                    .partition(|(key, _)| matches!(key.vis, Visibility::Public(_)));
+                if rest.is_empty() {
+                    vec![(header, blank_line, public)]
+                } else {
+                    vec![(header, blank_line, rest), (Vec::new(), true, public)]
+                }
             })
-            .collect::<Vec<_>>();
-        let span = items
+            .collect()
+    } else {
+        groups
+    };
+
+    groups
+        .into_iter()
+        .map(|(header, blank_line, entries)| {
+            let statements = render_statements(entries, opts, indentation, stats);
+            (header, blank_line, statements)
+        })
+        .filter(|(_, _, statements)| !statements.is_empty())
+        .fold(
+            String::new(),
+            |mut output, (header, blank_line, statements)| {
+                if !output.is_empty() {
+                    output.push_str(if blank_line { "\n\n" } else { "\n" });
+                    output.push_str(indent);
+                }
+                output
+                    + &header
+                        .into_iter()
+                        .chain(statements)
+                        .collect::<Vec<_>>()
+                        .join(&format!("\n{indent}"))
+            },
+        )
+}
+
+/// Splits entries into blocks of consecutive entries importing from the same crate.
+fn split_by_crate(entries: Vec<(UseKey, Entry)>) -> Vec<Vec<(UseKey, Entry)>> {
+    let mut blocks: Vec<Vec<(UseKey, Entry)>> = Vec::new();
+
+    for entry in entries {
+        match blocks.last_mut() {
+            Some(block) if block.last().unwrap().0.name.ident() == entry.0.name.ident() => {
+                block.push(entry)
+            }
+            _ => blocks.push(vec![entry]),
+        }
+    }
+
+    blocks
+}
+
+/// Renders the statements of a block, counting them in `stats`.
+fn render_statements(
+    entries: Vec<(UseKey, Entry)>,
+    opts: &FormatOptions,
+    indentation: &Indentation,
+    stats: &mut FormatStats,
+) -> Vec<String> {
+    let indent = indentation.base();
+    let mut statements = Vec::new();
+
+    // only pad visibilities if they differ, which they can't when they're all as wide
+    let vis_width = if opts.align_visibility {
+        entries
             .iter()
-            .map(Spanned::span)
-            .reduce(|a, b| a.join(b).unwrap())
-            .unwrap();
-        let mut use_map = items.into_iter().collect::<UseMap>();
+            .map(|(key, _)| key.vis.as_display().to_string().len())
+            .max()
+            .unwrap_or_default()
+    } else {
+        0
+    };
 
-        let prev_use_span = last_use_span.replace(span);
-        let preceding_byte_range = prev_use_span
-            .map(|span| span.byte_range().end)
-            .unwrap_or_default()..span.byte_range().start;
+    for (
+        key,
+        Entry {
+            category,
+            items,
+            comments,
+        },
+    ) in entries
+    {
+        let first_statement = statements.len();
 
-        write!(output, "{}", &file[preceding_byte_range])?;
+        if opts.reorder_only {
+            statements.extend(items.iter().map(|item| {
+                item.span()
+                    .source_text()
+                    .unwrap_or_else(|| item.as_display().to_string())
+            }));
+        } else {
+            statements.extend(render_tree(
+                &key,
+                items,
+                opts,
+                indentation,
+                vis_width,
+                stats,
+            ));
+        }
 
-        for category_map in [Category::Std, Category::External, Category::Crate]
-            .map(|category| use_map.take(category))
-        {
-            for (key, items) in category_map {
-                let tree = UseTree::from(Node::from_iter(
-                    items.into_iter().map(|ItemUse { tree, .. }| tree),
-                ));
-                let item = ItemUse {
-                    attrs: Vec::default(),
-                    vis: key.vis,
-                    use_token: <Token![use]>::default(),
-                    leading_colon: key.leading_colon.into(),
-                    tree,
-                    semi_token: <Token![;]>::default(),
-                };
-                writeln!(output, "{}", item.as_display())?;
+        if let Some(statement) = statements.get_mut(first_statement) {
+            for comment in comments.iter().rev() {
+                statement.insert_str(0, &format!("{comment}\n{indent}"));
             }
-            writeln!(output)?;
         }
+
+        // an entry may be rendered as any number of statements (e.g. with `standalone_renames`)
+        let emitted = statements.len() - first_statement;
+        stats.emitted += emitted;
+        *stats.categories.entry(category).or_default() += emitted;
     }
 
-    write!(
-        output,
-        "{}",
-        &file[last_use_span
-            .map(|span| span.byte_range().end)
-            .unwrap_or_default()..]
-    )?;
+    statements
+}
 
-    Ok(())
+/// Sorts the trees of standalone statements by their text (after their depth, with
+/// [`SortMode::ByDepthThenName`]).
+fn sort_trees(trees: &mut [UseTree], mode: SortMode) {
+    trees.sort_by_cached_key(|tree| {
+        let depth = match mode {
+            SortMode::ByDepthThenName => tree::depth(tree),
+            SortMode::Ascii | SortMode::SmartCase => 0,
+        };
+        (depth, tree.as_display().to_string())
+    });
+}
+
+/// Merges the items of an entry into a tree, rendering a statement for each of its roots, with
+/// their visibility padded to `vis_width` columns. Imports dropped along the way are recorded in
+/// `stats`.
+fn render_tree(
+    key: &UseKey,
+    items: Vec<ItemUse>,
+    opts: &FormatOptions,
+    indentation: &Indentation,
+    vis_width: usize,
+    stats: &mut FormatStats,
+) -> Vec<String> {
+    let indent = indentation.base();
+    let mut statements = Vec::new();
+
+    let mut tree = Tree::from_iter(items.into_iter().map(|ItemUse { tree, .. }| tree));
+    if opts.drop_glob_covered {
+        let leading_colon = if key.has_leading_colon() { "::" } else { "" };
+        stats.dropped.extend(
+            tree.drop_glob_covered()
+                .into_iter()
+                .map(|path| format!("{leading_colon}{path}")),
+        );
+    }
+    if opts.drop_lone_self {
+        tree.drop_lone_self();
+    }
+    if let Some(comparator) = &opts.comparator {
+        let key = |name: &Name| UseKey {
+            name: name.clone(),
+            ..key.clone()
+        };
+        tree.sort_by(
+            |name, other| comparator.cmp(&key(name), &key(other)),
+            opts.sort_renames_by_alias,
+            opts.sort_mode,
+        );
+    } else {
+        tree.sort(opts.sort_renames_by_alias, opts.sort_mode);
+    }
+    let mut modules = Vec::new();
+    if !opts.collapse_self {
+        modules = tree.take_self_imports();
+        sort_trees(&mut modules, opts.sort_mode);
+    }
+    let mut renames = Vec::new();
+    if opts.standalone_renames {
+        renames = tree.take_renames();
+        sort_trees(&mut renames, opts.sort_mode);
+    }
+
+    let trees = if !opts.merge_pub_use && matches!(key.vis, Visibility::Public(_)) {
+        let mut paths = tree.into_paths();
+        sort_trees(&mut paths, opts.sort_mode);
+        paths
+    } else {
+        tree.into_use_trees().collect()
+    };
+
+    for tree in modules.into_iter().chain(trees).chain(renames) {
+        let tree = if opts.collapse_single_groups {
+            tree::collapse_single_groups(tree)
+        } else {
+            tree
+        };
+        let item = ItemUse {
+            attrs: key.attrs.clone(),
+            vis: key.vis.clone(),
+            use_token: <Token![use]>::default(),
+            leading_colon: key.leading_colon.into(),
+            tree,
+            semi_token: <Token![;]>::default(),
+        };
+        if !opts.always_single_line {
+            statements.push(display::wrapped(&item, opts, indentation, vis_width));
+            continue;
+        }
+
+        let statement = display::single_line(&item, indentation, vis_width);
+        if is_too_wide(&statement, opts) {
+            statements.push(format!("#[rustfmt::skip]\n{indent}{statement}"));
+        } else {
+            statements.push(statement);
+        }
+    }
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_use_runs_unjoinable_spans() {
+        let first = syn::parse_str::<ItemUse>("use a;").unwrap();
+        let last = syn::parse_str::<ItemUse>("use b;").unwrap();
+        assert!(first.span().join(last.span()).is_none());
+
+        let runs = use_runs([Some(first.clone()), Some(last.clone())]);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(
+            runs[0].0,
+            first.span().byte_range().start..last.span().byte_range().end
+        );
+        assert_eq!(runs[0].1, [first, last]);
+    }
 }
@@ -1,14 +1,40 @@
+mod config;
 mod output;
 
 use std::{
     error::Error,
-    io::{stdin, Read as _},
+    fs,
+    io::{self, stdin, stdout, Read as _, Write as _},
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Mutex,
+    },
+    thread,
 };
 
-use clap::Parser;
-use user_spray::format;
+use clap::{Parser, ValueEnum};
+use globset::{Glob, GlobBuilder, GlobSetBuilder};
+use serde::Serialize;
+use user_spray::{
+    edits_with_stats, format_str_with_stats, shadowed_imports, FormatOptions, FormatStats,
+    Shadowing,
+};
+
+use self::{config::ConfigOverride, output::Rustfmt};
 
-use self::output::Output;
+/// Where formatted output goes, mirroring rustfmt's `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Emit {
+    /// Write the formatted files to stdout, each preceded by a `PATH:` line and a blank line
+    /// (like rustfmt does).
+    Stdout,
+    /// Overwrite the files in place.
+    Files,
+    /// Write a JSON description of the changed import blocks to stdout. rustfmt is not run.
+    Json,
+}
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -16,17 +42,374 @@ struct Args {
     #[arg(long, help = "Don't pass results through rustfmt")]
     skip_rustfmt: bool,
 
+    #[arg(
+        long = "config",
+        value_name = "KEY=VALUE",
+        help = "Override a formatting option (can be repeated)"
+    )]
+    config: Vec<ConfigOverride>,
+
+    #[arg(long, help = "Print a summary of the merged imports to stderr")]
+    stats: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Log whether each input was reformatted, unchanged or failed, and the imports \
+                dropped from it, to stderr"
+    )]
+    verbose: bool,
+
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["verbose", "stats", "warn_shadow"],
+        help = "Log nothing but errors to stderr (hiding rustfmt's warnings)"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        help = "Warn about imports of the same name from different paths (ignoring `#[cfg]`-gated \
+                imports) to stderr"
+    )]
+    warn_shadow: bool,
+
+    #[arg(
+        long,
+        help = "Format files concurrently, reporting the results in order of their paths once all \
+                are formatted"
+    )]
+    parallel: bool,
+
+    #[arg(
+        long,
+        help = "Run rustfmt once for all files rather than once per file, reporting the results \
+                once all are formatted"
+    )]
+    batch_rustfmt: bool,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        value_parser = parse_glob,
+        help = "Skip files matching a glob, e.g. `**/generated/*.rs` (can be repeated)"
+    )]
+    exclude: Vec<Glob>,
+
+    #[arg(long, value_enum, default_value_t = Emit::Stdout, help = "What to emit")]
+    emit: Emit,
+
+    #[arg(help = "Files to format (stdin is formatted if none are given)")]
+    files: Vec<PathBuf>,
+
     #[arg(last = true)]
     rustfmt_args: Vec<String>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+/// The `--emit=json` description of a single changed import block.
+#[derive(Serialize)]
+struct JsonEdit {
+    start: usize,
+    end: usize,
+    original: String,
+    formatted: String,
+}
+
+/// The `--emit=json` description of a single input.
+#[derive(Serialize)]
+struct JsonFile {
+    /// `None` for stdin.
+    file: Option<PathBuf>,
+    edits: Vec<JsonEdit>,
+}
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Parses an `--exclude` glob. Unlike in the default syntax, `*` doesn't match path separators
+/// (only `**` does), as in most shells.
+fn parse_glob(glob: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(glob).literal_separator(true).build()
+}
+
+impl Args {
+    /// Whether all inputs are formatted before any of them is reported, rather than one at a
+    /// time.
+    fn buffered(&self) -> bool {
+        self.parallel || self.batch_rustfmt
+    }
+}
+
+/// The outcome of formatting a single input, to be reported once it's formatted.
+struct Report {
+    /// Whether the final output (e.g. after rustfmt) differs from the input.
+    changed: bool,
+    /// The final output, for `--emit=stdout` and `--emit=files`.
+    formatted: Option<String>,
+    json: Option<JsonFile>,
+    stats: Option<FormatStats>,
+    /// The shadowed imports found with `--warn-shadow`.
+    shadowings: Vec<Shadowing>,
+    /// The imports dropped with `drop_glob_covered`, logged with `--verbose`.
+    dropped: Vec<String>,
+}
+
+fn main() -> Result<(), BoxError> {
+    let mut args = Args::parse();
+
+    let mut opts = FormatOptions::default();
+    for config in args.config.drain(..) {
+        config.apply(&mut opts);
+    }
 
-    let mut file = String::new();
-    stdin().read_to_string(&mut file)?;
+    if args.emit == Emit::Files && args.files.is_empty() {
+        return Err("`--emit=files` requires files to format".into());
+    }
 
-    let output = Output::new(args)?;
+    let mut inputs = if args.files.is_empty() {
+        let mut file = String::new();
+        stdin().read_to_string(&mut file)?;
+        vec![(None, file)]
+    } else {
+        let mut exclude = GlobSetBuilder::new();
+        for glob in &args.exclude {
+            exclude.add(glob.clone());
+        }
+        let exclude = exclude.build()?;
+
+        args.files
+            .iter()
+            .filter(|path| {
+                let excluded = exclude.is_match(path);
+                if excluded && args.verbose {
+                    eprintln!("{}: excluded", path.display());
+                }
+                !excluded
+            })
+            .map(|path| Ok((Some(path.clone()), fs::read_to_string(path)?)))
+            .collect::<Result<Vec<_>, BoxError>>()?
+    };
+
+    let rustfmt = (!args.skip_rustfmt).then(|| Rustfmt::new(&args.rustfmt_args, args.quiet));
+    // with `--batch-rustfmt`, rustfmt is only run once all inputs are formatted
+    let input_rustfmt = rustfmt.as_ref().filter(|_| !args.batch_rustfmt);
+
+    let mut json = Vec::new();
+    if args.buffered() {
+        let mut reports = if args.parallel {
+            inputs.sort_by(|(path, _), (other_path, _)| path.cmp(other_path));
+            format_parallel(&inputs, &args, &opts, input_rustfmt)
+        } else {
+            inputs
+                .iter()
+                .map(|(path, file)| format_input(path.as_ref(), file, &args, &opts, input_rustfmt))
+                .collect()
+        };
+        if let Some(rustfmt) = rustfmt.as_ref().filter(|_| args.batch_rustfmt) {
+            rustfmt_reports(&inputs, &mut reports, rustfmt);
+        }
+
+        let mut failed = 0;
+        for ((path, _), report) in inputs.iter().zip(reports) {
+            failed += usize::from(report_input(path.as_ref(), report, &args, &mut json).is_err());
+        }
+        if failed > 0 {
+            return Err(format!("failed to format {failed} input(s)").into());
+        }
+    } else {
+        for (path, file) in &inputs {
+            let report = format_input(path.as_ref(), file, &args, &opts, input_rustfmt);
+            report_input(path.as_ref(), report, &args, &mut json)?;
+        }
+    }
+
+    if args.emit == Emit::Json {
+        serde_json::to_writer_pretty(stdout().lock(), &json)?;
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Formats `inputs` on a pool of threads, returning their reports in the same order.
+fn format_parallel(
+    inputs: &[(Option<PathBuf>, String)],
+    args: &Args,
+    opts: &FormatOptions,
+    rustfmt: Option<&Rustfmt>,
+) -> Vec<Result<Report, BoxError>> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(inputs.iter().map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.min(inputs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, atomic::Ordering::Relaxed);
+                let Some((path, file)) = inputs.get(i) else {
+                    break;
+                };
+                let report = format_input(path.as_ref(), file, args, opts, rustfmt);
+                reports.lock().unwrap()[i] = Some(report);
+            });
+        }
+    });
+
+    reports
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(Option::unwrap)
+        .collect()
+}
+
+/// Runs the output of all successfully formatted inputs through rustfmt at once, for
+/// `--batch-rustfmt`.
+fn rustfmt_reports(
+    inputs: &[(Option<PathBuf>, String)],
+    reports: &mut [Result<Report, BoxError>],
+    rustfmt: &Rustfmt,
+) {
+    let (batch, formatted): (Vec<_>, Vec<_>) = reports
+        .iter()
+        .enumerate()
+        .filter_map(|(i, report)| Some((i, report.as_ref().ok()?.formatted.as_deref()?)))
+        .unzip();
+
+    let outputs = rustfmt.format_all(&formatted);
+    for (i, output) in batch.into_iter().zip(outputs) {
+        match (&mut reports[i], output) {
+            (Ok(report), Ok(output)) => {
+                report.changed = output != inputs[i].1;
+                report.formatted = Some(output);
+            }
+            (report, Err(err)) => *report = Err(err.into()),
+            (Err(_), Ok(_)) => unreachable!(),
+        }
+    }
+}
+
+/// Formats a single input according to `--emit`, running its output through `rustfmt` (if
+/// any). Its output is only written out once it's reported.
+fn format_input(
+    path: Option<&PathBuf>,
+    file: &str,
+    args: &Args,
+    opts: &FormatOptions,
+    rustfmt: Option<&Rustfmt>,
+) -> Result<Report, BoxError> {
+    let mut report = Report {
+        changed: false,
+        formatted: None,
+        json: None,
+        stats: None,
+        shadowings: Vec::new(),
+        dropped: Vec::new(),
+    };
+
+    if args.warn_shadow {
+        report.shadowings = shadowed_imports(file)?;
+    }
+
+    let stats = match args.emit {
+        Emit::Stdout | Emit::Files => {
+            let (mut formatted, stats) = format_str_with_stats(file, opts)?;
+            if let Some(rustfmt) = rustfmt {
+                formatted = rustfmt.format(&formatted)?;
+            }
+            // compare the final output, so that an input is reported the same way with either
+            // `--emit`
+            report.changed = formatted != file;
+            report.formatted = Some(formatted);
+            stats
+        }
+        Emit::Json => {
+            let (edits, stats) = edits_with_stats(file, opts)?;
+            let edits = edits
+                .into_iter()
+                .map(|edit| JsonEdit {
+                    start: edit.range.start,
+                    end: edit.range.end,
+                    original: file[edit.range].to_owned(),
+                    formatted: edit.replacement,
+                })
+                .collect::<Vec<_>>();
+            report.changed = !edits.is_empty();
+            report.json = Some(JsonFile {
+                file: path.cloned(),
+                edits,
+            });
+            stats
+        }
+    };
+
+    if args.verbose {
+        report.dropped.clone_from(&stats.dropped);
+    }
+    if args.stats {
+        report.stats = Some(stats);
+    }
+
+    Ok(report)
+}
+
+/// Reports the outcome of formatting an input: writes out its output and logs to stderr (with
+/// `--warn-shadow`, `--verbose` or `--stats`). Errors are logged with `--verbose`, and always
+/// when [buffered](Args::buffered), since other inputs are still reported after them.
+fn report_input(
+    path: Option<&PathBuf>,
+    report: Result<Report, BoxError>,
+    args: &Args,
+    json: &mut Vec<JsonFile>,
+) -> Result<(), BoxError> {
+    let name = path.map_or_else(|| "<stdin>".into(), |path| path.display().to_string());
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            if args.verbose || args.buffered() {
+                eprintln!("{name}: error: {err}");
+            }
+            return Err(err);
+        }
+    };
+
+    for shadowing in &report.shadowings {
+        eprintln!("{name}: warning: {shadowing}");
+    }
+    if args.verbose {
+        if report.changed {
+            eprintln!("{name}: reformatted");
+        } else {
+            eprintln!("{name}: unchanged");
+        }
+        for path in &report.dropped {
+            eprintln!("{name}: dropped `{path}`, which a glob import already covers");
+        }
+    }
+    match (args.emit, report.formatted) {
+        (Emit::Stdout, Some(formatted)) => {
+            write_header(path)?;
+            stdout().lock().write_all(formatted.as_bytes())?;
+        }
+        (Emit::Files, Some(formatted)) if report.changed => fs::write(path.unwrap(), formatted)?,
+        _ => {}
+    }
+    json.extend(report.json);
+    if let Some(stats) = report.stats {
+        match path {
+            Some(path) => eprintln!("{}: {stats}", path.display()),
+            None => eprintln!("{stats}"),
+        }
+    }
+
+    Ok(())
+}
 
-    format(&file, output)
+/// Writes the header preceding the `--emit=stdout` output of a file, so that the output of many
+/// files can be told apart (like rustfmt does). Stdin's output has no header.
+fn write_header(path: Option<&PathBuf>) -> io::Result<()> {
+    if let Some(path) = path {
+        writeln!(stdout().lock(), "{}:\n", path.display())?;
+    }
+    Ok(())
 }
@@ -1,6 +1,8 @@
 use std::cmp::Ordering;
 
-use syn::{Ident, Token, Visibility};
+use syn::{Attribute, Ident, Meta, Token, Visibility};
+
+use crate::{display::AsDisplay, CrateOrder, FormatOptions, SortMode};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) enum LeadingColon {
@@ -24,22 +26,128 @@ impl From<LeadingColon> for Option<Token![::]> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub(crate) enum Name {
+/// The first segment of a root `use` tree.
+///
+/// Names are ordered explicitly (see [`Name::cmp_with`]), not by variant declaration order:
+/// named imports (idents and renames) are ordered by the ident they import, with an ident
+/// sorting before its renames, and globs come after all named imports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Name {
     Ident(Ident),
     Glob,
     Rename { ident: Ident, rename: Ident },
 }
 
+impl Name {
+    /// The ident being imported (i.e. not the alias, for renames).
+    pub fn ident(&self) -> Option<&Ident> {
+        match self {
+            Self::Ident(ident) | Self::Rename { ident, .. } => Some(ident),
+            Self::Glob => None,
+        }
+    }
+
+    fn sort_ident(&self, by_alias: bool) -> Option<&Ident> {
+        match self {
+            Self::Ident(ident) => Some(ident),
+            Self::Glob => None,
+            Self::Rename { rename, .. } if by_alias => Some(rename),
+            Self::Rename { ident, .. } => Some(ident),
+        }
+    }
+
+    /// Orders names by the ident they import, so that `Foo as Bar` sorts right after `Foo`
+    /// (or, if `by_alias` is set, right after `Bar`). Globs sort after all named imports.
+    pub(crate) fn cmp_with(&self, other: &Self, by_alias: bool, mode: SortMode) -> Ordering {
+        match (self.sort_ident(by_alias), other.sort_ident(by_alias)) {
+            (Some(ident), Some(other_ident)) => mode.cmp_idents(ident, other_ident),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(|| match (self, other) {
+            (Self::Ident(_), Self::Rename { .. }) => Ordering::Less,
+            (Self::Rename { .. }, Self::Ident(_)) => Ordering::Greater,
+            (
+                Self::Rename { ident, rename },
+                Self::Rename {
+                    ident: other_ident,
+                    rename: other_rename,
+                },
+            ) => mode
+                .cmp_idents(ident, other_ident)
+                .then_with(|| mode.cmp_idents(rename, other_rename)),
+            _ => Ordering::Equal,
+        })
+    }
+
+    /// Where a root name is ordered by `order`, before it's compared by ident: keyword roots
+    /// (`crate`, `super` and `self`) may be ranked before all others.
+    fn root_rank(&self, order: CrateOrder) -> u8 {
+        match order {
+            CrateOrder::Alphabetical => 0,
+            CrateOrder::KeywordsFirst => self
+                .ident()
+                .and_then(|ident| ["crate", "super", "self"].iter().position(|kw| ident == kw))
+                .map_or(3, |rank| rank as u8),
+        }
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_with(other, false, SortMode::default())
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// What `use` items are merged and sorted by: items with equal keys are merged into a single
+/// statement.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub(crate) struct UseKey {
+pub struct UseKey {
+    /// Items with different attributes are never merged, since e.g. `#[cfg]`s may differ.
+    pub(crate) attrs: Vec<Attribute>,
     pub(crate) vis: Visibility,
     pub(crate) leading_colon: LeadingColon,
     pub(crate) name: Name,
 }
 
-impl Ord for UseKey {
-    fn cmp(&self, other: &Self) -> Ordering {
+impl UseKey {
+    pub fn attrs(&self) -> &[Attribute] {
+        &self.attrs
+    }
+
+    pub fn vis(&self) -> &Visibility {
+        &self.vis
+    }
+
+    /// Whether the path starts with `::`.
+    pub fn has_leading_colon(&self) -> bool {
+        self.leading_colon == LeadingColon::Yes
+    }
+
+    /// The first segment of the path (e.g. `std` in `use std::mem;`).
+    pub fn name(&self) -> &Name {
+        &self.name
+    }
+
+    /// The predicates of the item's `#[cfg(...)]` attributes, as text.
+    fn cfg_predicates(&self) -> impl Iterator<Item = String> + '_ {
+        self.attrs.iter().filter_map(|attr| match &attr.meta {
+            Meta::List(list) if list.path.is_ident("cfg") => Some(list.tokens.to_string()),
+            _ => None,
+        })
+    }
+
+    /// The built-in order: private items first, then restricted and public ones, with cfg-gated
+    /// items after ungated ones, each sorted by [`Name`] (with keyword roots ranked according
+    /// to [`FormatOptions::crate_order`]).
+    pub fn cmp_with(&self, other: &Self, opts: &FormatOptions) -> Ordering {
         macro_rules! cmp {
             ($ord:expr) => {
                 match $ord {
@@ -68,12 +176,8 @@ impl Ord for UseKey {
                     &other_vis.path.leading_colon.is_some()
                 );
 
-                assert!([&vis.path.segments, &other_vis.path.segments]
-                    .iter()
-                    .copied()
-                    .flatten()
-                    .all(|segment| segment.arguments.is_none()));
-
+                // `pub(in ...)` paths can't have generic arguments in valid Rust, so only
+                // the idents are compared (arguments, if any, are ignored)
                 cmp!(vis
                     .path
                     .segments
@@ -85,7 +189,29 @@ impl Ord for UseKey {
 
         cmp!(self.leading_colon, &other.leading_colon);
 
-        self.name.cmp(&other.name)
+        // cfg-gated items come after ungated ones, grouped by their predicates, so that e.g. the
+        // unix and windows variants of an import are kept together
+        cmp!(self.cfg_predicates().cmp(other.cfg_predicates()));
+
+        cmp!(self
+            .name
+            .root_rank(opts.crate_order)
+            .cmp(&other.name.root_rank(opts.crate_order)));
+        cmp!(self
+            .name
+            .cmp_with(&other.name, opts.sort_renames_by_alias, opts.sort_mode));
+
+        // items without attributes come first, the rest are ordered by their attributes' text
+        self.attrs
+            .iter()
+            .map(|attr| attr.as_display().to_string())
+            .cmp(other.attrs.iter().map(|attr| attr.as_display().to_string()))
+    }
+}
+
+impl Ord for UseKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_with(other, &FormatOptions::default())
     }
 }
 
@@ -94,3 +220,123 @@ impl PartialOrd for UseKey {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use syn::{parse_quote, PathArguments};
+
+    use super::*;
+
+    fn name_key(name: Name) -> UseKey {
+        UseKey {
+            attrs: Vec::new(),
+            vis: Visibility::Inherited,
+            leading_colon: LeadingColon::No,
+            name,
+        }
+    }
+
+    fn ident(ident: &str) -> Ident {
+        Ident::new(ident, proc_macro2::Span::call_site())
+    }
+
+    fn rename(ident_: &str, rename: &str) -> Name {
+        Name::Rename {
+            ident: ident(ident_),
+            rename: ident(rename),
+        }
+    }
+
+    #[test]
+    fn test_renames_sort_by_original_ident() {
+        let mut keys = [
+            name_key(rename("a", "z")),
+            name_key(Name::Ident(ident("b"))),
+            name_key(rename("a", "y")),
+            name_key(Name::Glob),
+            name_key(Name::Ident(ident("a"))),
+        ];
+
+        keys.sort();
+        assert_eq!(
+            keys.clone().map(|key| key.name),
+            [
+                Name::Ident(ident("a")),
+                rename("a", "y"),
+                rename("a", "z"),
+                Name::Ident(ident("b")),
+                Name::Glob,
+            ]
+        );
+
+        let opts = FormatOptions {
+            sort_renames_by_alias: true,
+            ..FormatOptions::default()
+        };
+        keys.sort_by(|key, other| key.cmp_with(other, &opts));
+        assert_eq!(
+            keys.clone().map(|key| key.name),
+            [
+                Name::Ident(ident("a")),
+                Name::Ident(ident("b")),
+                rename("a", "y"),
+                rename("a", "z"),
+                Name::Glob,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_name_order() {
+        use Ordering::*;
+
+        let cases = [
+            (Name::Ident(ident("a")), rename("a", "b"), Less),
+            (rename("a", "z"), Name::Ident(ident("b")), Less),
+            (rename("z", "a"), Name::Glob, Less),
+            (Name::Ident(ident("z")), Name::Glob, Less),
+            (rename("a", "b"), rename("a", "c"), Less),
+            (Name::Glob, Name::Glob, Equal),
+        ];
+
+        for (name, other, ord) in cases {
+            assert_eq!(name.cmp(&other), ord, "{name:?} vs {other:?}");
+            assert_eq!(other.cmp(&name), ord.reverse(), "{other:?} vs {name:?}");
+        }
+    }
+
+    fn key(vis: Visibility) -> UseKey {
+        UseKey {
+            attrs: Vec::new(),
+            vis,
+            leading_colon: LeadingColon::No,
+            name: Name::Glob,
+        }
+    }
+
+    #[test]
+    fn test_restricted_vis_with_generic_arguments() {
+        let mut vis: Visibility = parse_quote!(pub(in crate::a));
+        let Visibility::Restricted(vis_restricted) = &mut vis else {
+            unreachable!()
+        };
+        vis_restricted.path.segments[1].arguments =
+            PathArguments::AngleBracketed(parse_quote!(<T>));
+
+        let mut keys = [
+            key(parse_quote!(pub(in crate::b))),
+            key(vis),
+            key(parse_quote!(pub)),
+            key(parse_quote!(pub(in crate::a))),
+        ];
+        keys.sort();
+
+        assert!(matches!(
+            &keys[0].vis,
+            Visibility::Restricted(vis) if !vis.path.segments[1].arguments.is_none()
+        ));
+        assert_eq!(keys[1].vis, parse_quote!(pub(in crate::a)));
+        assert_eq!(keys[2].vis, parse_quote!(pub(in crate::b)));
+        assert_eq!(keys[3].vis, parse_quote!(pub));
+    }
+}
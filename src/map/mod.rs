@@ -1,102 +1,550 @@
 mod key;
 
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, iter,
+    str::FromStr,
+};
 
-use proc_macro2::Span;
-use syn::{Ident, ItemUse, Token, UseName, UsePath, UseRename};
+use fn_formats::DisplayFmt;
+use syn::{
+    punctuated::Punctuated, ItemUse, UseGroup, UseName, UsePath, UseRename, UseTree, Visibility,
+};
 
-pub(crate) use self::key::{LeadingColon, Name, UseKey};
+pub(crate) use self::key::LeadingColon;
+pub use self::key::{Name, UseKey};
+use crate::{
+    tree::{self, split_root_group},
+    FormatOptions, SortMode,
+};
 
+/// The block an import is grouped into.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(super) enum Category {
+pub enum Category {
+    /// `std`, `core` and `alloc` (and any configured std-like crates).
     Std,
+    /// Third-party crates.
     External,
+    /// `crate`, `self` and `super`.
     Crate,
+    /// A category of a custom [`Classifier`](crate::Classifier), with the given name. Its block is
+    /// emitted where it's listed in [`FormatOptions::category_order`], or after all other blocks
+    /// (ordered by name) if it isn't.
+    Custom(&'static str),
 }
 
-impl From<&Name> for Category {
-    fn from(value: &Name) -> Self {
-        macro_rules! i {
-            ($ident:ident) => {
-                Ident::new(stringify!($ident), Span::call_site())
-            };
+impl Category {
+    /// The built-in categories.
+    pub const ALL: [Self; 3] = [Self::Std, Self::External, Self::Crate];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Std => "std",
+            Self::External => "external",
+            Self::Crate => "crate",
+            Self::Custom(name) => name,
         }
+    }
+}
 
-        macro_rules! ti {
-            ($tt:tt) => {
-                Ident::from(<Token![$tt]>::default())
-            };
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The error returned when parsing an unknown [`Category`] name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCategoryError(String);
+
+impl fmt::Display for ParseCategoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown category `{}` (expected one of {})",
+            self.0,
+            DisplayFmt(|f| {
+                for (i, category) in Category::ALL.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "`{category}`")?;
+                }
+                Ok(())
+            })
+        )
+    }
+}
+
+impl Error for ParseCategoryError {}
+
+impl FromStr for Category {
+    type Err = ParseCategoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.as_str() == s)
+            .ok_or_else(|| ParseCategoryError(s.to_owned()))
+    }
+}
+
+/// Why a list isn't a valid [`FormatOptions::category_order`] (when deserialized or parsed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CategoryOrderError {
+    Duplicate(Category),
+    Missing(Category),
+}
+
+impl fmt::Display for CategoryOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate(category) => write!(f, "category `{category}` appears more than once"),
+            Self::Missing(category) => write!(f, "category `{category}` is missing"),
         }
+    }
+}
 
-        let ident = match value {
-            Name::Ident(ident) => ident,
-            Name::Glob => return Self::External,
-            Name::Rename { ident: from, .. } => from,
+impl Category {
+    /// Checks that `order` lists every built-in category exactly once (and any custom one at most
+    /// once).
+    pub(crate) fn check_order(order: &[Self]) -> Result<(), CategoryOrderError> {
+        for (i, category) in order.iter().enumerate() {
+            if order[..i].contains(category) {
+                return Err(CategoryOrderError::Duplicate(*category));
+            }
+        }
+        match Self::ALL
+            .into_iter()
+            .find(|category| !order.contains(category))
+        {
+            Some(category) => Err(CategoryOrderError::Missing(category)),
+            None => Ok(()),
+        }
+    }
+
+    /// The built-in categorization of an import, by its first path segment. This is what
+    /// imports are categorized by unless a [`Classifier`](crate::Classifier) is set, which can
+    /// delegate to it.
+    ///
+    /// `path` holds the segments of the import (see [`Classifier`](crate::Classifier)). A leading
+    /// `::` always refers to an external crate, so such imports are never [`Category::Crate`].
+    /// A root glob (`use *;`) imports the items of the crate root.
+    pub fn of_path(path: &[&str], opts: &FormatOptions) -> Self {
+        let (leading_colon, path) = match path {
+            ["", path @ ..] => (true, path),
+            path => (false, path),
         };
+        // an empty root group (`use {};`) is like a root glob
+        let ident = path.first().copied().unwrap_or("*");
 
-        if [i!(std), i!(core), i!(alloc)].contains(ident) {
+        if ident == "*" {
+            if leading_colon {
+                Self::External
+            } else {
+                Self::Crate
+            }
+        } else if ["std", "core", "alloc"].contains(&ident) || opts.std_crates.contains(ident) {
             Self::Std
-        } else if [ti![self], ti![super], ti![crate]].contains(ident) {
+        } else if !leading_colon && ["self", "super", "crate"].contains(&ident) {
             Self::Crate
         } else {
             Self::External
         }
     }
+
+    /// Categorizes an import by its path (and its item's visibility), with the
+    /// [`CategorySet`](crate::CategorySet) or else the [`Classifier`](crate::Classifier), if any.
+    fn classify(path: &[&str], vis: &Visibility, opts: &FormatOptions) -> Self {
+        match (&opts.categories, &opts.classifier) {
+            (Some(set), _) => set.classify(path, vis, opts),
+            (None, Some(classifier)) => classifier.classify(path),
+            (None, None) => Self::of_path(path, opts),
+        }
+    }
+
+    /// Categorizes an item by the first import of its (first) root tree.
+    pub(crate) fn of_item(item: &ItemUse, opts: &FormatOptions) -> Self {
+        let mut path = leading_path(item);
+        let mut tree = &item.tree;
+        loop {
+            match tree {
+                UseTree::Path(UsePath {
+                    ident, tree: next, ..
+                }) => {
+                    path.push(ident.to_string());
+                    tree = next;
+                }
+                UseTree::Group(UseGroup { items, .. }) if !items.is_empty() => tree = &items[0],
+                tree => {
+                    path.extend(leaf_segment(tree));
+                    break;
+                }
+            }
+        }
+        Self::classify(
+            &path.iter().map(String::as_str).collect::<Vec<_>>(),
+            &item.vis,
+            opts,
+        )
+    }
+}
+
+/// The segments preceding an item's tree: an empty one for a leading `::`.
+fn leading_path(item: &ItemUse) -> Vec<String> {
+    item.leading_colon.iter().map(|_| String::new()).collect()
+}
+
+/// The last segment of an import ending with `tree`, if it's a leaf.
+fn leaf_segment(tree: &UseTree) -> Option<String> {
+    match tree {
+        UseTree::Name(UseName { ident }) | UseTree::Rename(UseRename { ident, .. }) => {
+            Some(ident.to_string())
+        }
+        UseTree::Glob(_) => Some("*".to_owned()),
+        UseTree::Path(_) | UseTree::Group(_) => None,
+    }
+}
+
+/// Splits an item's tree into the parts importing paths of each category (according to the
+/// [`Classifier`](crate::Classifier)), in order of their first import. A tree importing nothing
+/// (e.g. `foo::{}`) is categorized by its path.
+fn split_by_category(item: &ItemUse, opts: &FormatOptions) -> Vec<(Category, UseTree)> {
+    fn categories(
+        tree: &UseTree,
+        path: &mut Vec<String>,
+        classify: &dyn Fn(&[String]) -> Category,
+        found: &mut Vec<Category>,
+    ) {
+        match tree {
+            UseTree::Path(UsePath { ident, tree, .. }) => {
+                path.push(ident.to_string());
+                categories(tree, path, classify, found);
+                path.pop();
+            }
+            UseTree::Group(UseGroup { items, .. }) => {
+                for tree in items {
+                    categories(tree, path, classify, found);
+                }
+            }
+            leaf => {
+                path.extend(leaf_segment(leaf));
+                let category = classify(path);
+                path.pop();
+                if !found.contains(&category) {
+                    found.push(category);
+                }
+            }
+        }
+    }
+
+    /// The part of `tree` importing paths of `category`, if any.
+    fn filter(
+        tree: &UseTree,
+        path: &mut Vec<String>,
+        classify: &dyn Fn(&[String]) -> Category,
+        category: Category,
+    ) -> Option<UseTree> {
+        match tree {
+            UseTree::Path(UsePath {
+                ident,
+                colon2_token,
+                tree,
+            }) => {
+                path.push(ident.to_string());
+                let tree = filter(tree, path, classify, category);
+                path.pop();
+                Some(UseTree::Path(UsePath {
+                    ident: ident.clone(),
+                    colon2_token: *colon2_token,
+                    tree: Box::new(tree?),
+                }))
+            }
+            UseTree::Group(UseGroup { brace_token, items }) => {
+                let items = items
+                    .iter()
+                    .filter_map(|tree| filter(tree, path, classify, category))
+                    .collect::<Punctuated<_, _>>();
+                (!items.is_empty()).then_some(UseTree::Group(UseGroup {
+                    brace_token: *brace_token,
+                    items,
+                }))
+            }
+            leaf => {
+                path.extend(leaf_segment(leaf));
+                let matches = classify(path) == category;
+                path.pop();
+                matches.then(|| leaf.clone())
+            }
+        }
+    }
+
+    let classify = |path: &[String]| {
+        Category::classify(
+            &path.iter().map(String::as_str).collect::<Vec<_>>(),
+            &item.vis,
+            opts,
+        )
+    };
+    let mut found = Vec::new();
+    categories(&item.tree, &mut leading_path(item), &classify, &mut found);
+    if found.is_empty() {
+        return vec![(Category::of_item(item, opts), item.tree.clone())];
+    }
+
+    found
+        .into_iter()
+        .map(|category| {
+            let tree = filter(&item.tree, &mut leading_path(item), &classify, category).unwrap();
+            (category, tree)
+        })
+        .collect()
+}
+
+impl UseKey {
+    fn of(item: &ItemUse) -> Self {
+        Self {
+            attrs: item.attrs.clone(),
+            vis: item.vis.clone(),
+            leading_colon: LeadingColon::from(item.leading_colon),
+            name: root_name(&item.tree),
+        }
+    }
+}
+
+/// A [`UseKey`] ordered according to the formatting options, so that entries of a [`UseMap`]
+/// are kept sorted as they are inserted.
+#[derive(Debug, Clone)]
+struct SortedKey<'a> {
+    key: UseKey,
+    opts: &'a FormatOptions,
+}
+
+impl Ord for SortedKey<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.opts.cmp_keys(&self.key, &other.key)
+    }
+}
+
+impl PartialOrd for SortedKey<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortedKey<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
 }
 
-#[derive(Debug, Clone, Default)]
-pub(super) struct UseMap(HashMap<Category, HashMap<UseKey, Vec<ItemUse>>>);
+impl Eq for SortedKey<'_> {}
 
-impl UseMap {
-    pub(super) fn take(&mut self, category: Category) -> Vec<(UseKey, Vec<ItemUse>)> {
-        let mut items = self
-            .0
+/// The items sharing a [`UseKey`] (and category), which are merged into a single statement.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub(crate) category: Category,
+    pub(crate) items: Vec<ItemUse>,
+    /// Comments to keep above the merged statement.
+    pub(crate) comments: Vec<String>,
+}
+
+impl Entry {
+    /// The depth of the merged statement: that of its shallowest import.
+    fn depth(&self) -> usize {
+        self.items
+            .iter()
+            .map(|item| tree::depth(&item.tree))
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct UseMap<'a> {
+    opts: &'a FormatOptions,
+    /// The outer map is only ever accessed by category (never iterated), so its order doesn't
+    /// leak into the output. Entries are kept sorted by key, and keys comparing equal share an
+    /// entry, so the order of statements is independent of the input's order. (So is the order of
+    /// the members of a merged group, which are sorted when it's rendered.)
+    map: HashMap<Category, BTreeMap<SortedKey<'a>, Entry>>,
+}
+
+impl<'a> UseMap<'a> {
+    pub(super) fn new(opts: &'a FormatOptions) -> Self {
+        Self {
+            opts,
+            map: HashMap::new(),
+        }
+    }
+
+    pub(super) fn take(&mut self, category: Category) -> Vec<(UseKey, Entry)> {
+        let mut entries = self
+            .map
             .remove(&category)
             .unwrap_or_default()
             .into_iter()
+            .map(|(SortedKey { key, .. }, entry)| (key, entry))
             .collect::<Vec<_>>();
-        items.sort_by(|(key, _), (other_key, _)| key.cmp(other_key));
-        items
-    }
-}
-
-impl Extend<ItemUse> for UseMap {
-    fn extend<T: IntoIterator<Item = ItemUse>>(&mut self, iter: T) {
-        for item in iter {
-            // TODO: handle comments
-            assert!(item.attrs.is_empty());
-
-            let key = UseKey {
-                vis: item.vis.clone(),
-                leading_colon: LeadingColon::from(item.leading_colon),
-                name: match &item.tree {
-                    syn::UseTree::Path(UsePath { ident, .. }) => Name::Ident(ident.clone()),
-                    syn::UseTree::Name(UseName { ident }) => Name::Ident(ident.clone()),
-                    syn::UseTree::Rename(UseRename { ident, rename, .. }) => Name::Rename {
-                        ident: ident.clone(),
-                        rename: rename.clone(),
-                    },
-                    syn::UseTree::Glob(_) => Name::Glob,
-                    syn::UseTree::Group(_) => todo!(),
-                },
-            };
+        self.sort_by_depth(&mut entries);
+        entries
+    }
 
-            let category = Category::from(&key.name);
+    /// Orders the entries of a block by the depth of their statements (keeping them in order
+    /// otherwise), with [`SortMode::ByDepthThenName`].
+    fn sort_by_depth(&self, entries: &mut [(UseKey, Entry)]) {
+        if self.opts.sort_mode == SortMode::ByDepthThenName {
+            entries.sort_by_cached_key(|(_, entry)| entry.depth());
+        }
+    }
+
+    /// The categories left in the map that aren't built-in, ordered by name.
+    pub(super) fn custom_categories(&self) -> Vec<Category> {
+        let mut categories = self
+            .map
+            .keys()
+            .copied()
+            .filter(|category| !Category::ALL.contains(category))
+            .collect::<Vec<_>>();
+        categories.sort_by_key(|category| category.as_str());
+        categories
+    }
 
-            self.0
-                .entry(category)
-                .or_default()
-                .entry(key)
-                .or_default()
-                .push(item);
+    /// Takes the entries of all categories, sorted together.
+    pub(super) fn take_all(&mut self) -> Vec<(UseKey, Entry)> {
+        let mut entries = Category::ALL
+            .into_iter()
+            .chain(self.custom_categories())
+            .flat_map(|category| self.take(category))
+            .collect::<Vec<_>>();
+        entries.sort_by(|(key, _), (other_key, _)| self.opts.cmp_keys(key, other_key));
+        self.sort_by_depth(&mut entries);
+        entries
+    }
+}
+
+/// The name of a root tree. A root group (only kept whole in reorder-only mode) is named after its
+/// first member.
+fn root_name(tree: &UseTree) -> Name {
+    match tree {
+        UseTree::Path(UsePath { ident, .. }) | UseTree::Name(UseName { ident }) => {
+            Name::Ident(ident.clone())
         }
+        UseTree::Rename(UseRename { ident, rename, .. }) => Name::Rename {
+            ident: ident.clone(),
+            rename: rename.clone(),
+        },
+        UseTree::Glob(_) => Name::Glob,
+        UseTree::Group(UseGroup { items, .. }) => items.first().map_or(Name::Glob, root_name),
     }
 }
 
-impl FromIterator<ItemUse> for UseMap {
-    fn from_iter<T: IntoIterator<Item = ItemUse>>(iter: T) -> Self {
-        let mut map = Self::default();
-        map.extend(iter);
-        map
+/// Extends the map with items, each paired with the comments to keep above it. A root group's
+/// comments are kept with its first member.
+impl Extend<(ItemUse, Vec<String>)> for UseMap<'_> {
+    fn extend<T: IntoIterator<Item = (ItemUse, Vec<String>)>>(&mut self, iter: T) {
+        let items = iter
+            .into_iter()
+            .flat_map(|(item, comments)| match item.tree {
+                // in reorder-only mode, statements are kept whole (and sorted by their first root)
+                UseTree::Group(_) if !self.opts.reorder_only => split_root_group(item.tree.clone())
+                    .into_iter()
+                    .zip(iter::once(comments).chain(iter::repeat(Vec::new())))
+                    .map(|(tree, comments)| {
+                        (
+                            ItemUse {
+                                tree,
+                                ..item.clone()
+                            },
+                            comments,
+                        )
+                    })
+                    .collect(),
+                _ => vec![(item, comments)],
+            });
+
+        for (item, comments) in items {
+            // only a custom classifier may categorize the imports of a single root differently
+            let parts = if self.opts.classifier.is_some() && !self.opts.reorder_only {
+                split_by_category(&item, self.opts)
+                    .into_iter()
+                    .map(|(category, tree)| {
+                        let item = ItemUse {
+                            tree,
+                            ..item.clone()
+                        };
+                        (category, item)
+                    })
+                    .collect()
+            } else {
+                vec![(Category::of_item(&item, self.opts), item)]
+            };
+
+            let mut comments = Some(comments);
+            for (category, item) in parts {
+                let entry = self
+                    .map
+                    .entry(category)
+                    .or_default()
+                    .entry(SortedKey {
+                        key: UseKey::of(&item),
+                        opts: self.opts,
+                    })
+                    .or_insert_with(|| Entry {
+                        category,
+                        items: Vec::new(),
+                        comments: Vec::new(),
+                    });
+                entry.items.push(item);
+                entry.comments.extend(comments.take().into_iter().flatten());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_from_str() {
+        for category in Category::ALL {
+            assert_eq!(category.to_string().parse(), Ok(category));
+        }
+
+        assert_eq!(
+            "extern".parse::<Category>().unwrap_err().to_string(),
+            "unknown category `extern` (expected one of `std`, `external`, `crate`)"
+        );
+    }
+
+    #[test]
+    fn test_equal_keys_keep_their_order() {
+        let items: [ItemUse; 3] = [
+            syn::parse_quote!(
+                use a::c;
+            ),
+            syn::parse_quote!(
+                use a::b;
+            ),
+            syn::parse_quote!(
+                use a::{d};
+            ),
+        ];
+        let mut reversed = items.clone();
+        reversed.reverse();
+        let opts = FormatOptions::default();
+
+        // merged items keep the order they're imported in, whatever it is
+        for items in [items, reversed] {
+            let mut map = UseMap::new(&opts);
+            map.extend(items.iter().cloned().map(|item| (item, Vec::new())));
+
+            let entries = map.take(Category::External);
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].1.items, items);
+        }
     }
 }
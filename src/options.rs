@@ -0,0 +1,729 @@
+use std::{cmp::Ordering, collections::BTreeSet, error::Error, fmt, mem, str::FromStr, sync::Arc};
+
+use syn::{Ident, Visibility};
+
+use crate::{Category, UseKey};
+
+/// The indentation used for members of wrapped groups.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Indent {
+    Spaces(usize),
+    Tabs,
+    /// Match the file's existing indentation (that of its first indented line), falling back to
+    /// 4 spaces.
+    #[default]
+    Infer,
+}
+
+impl Indent {
+    /// Resolves [`Indent::Infer`] according to the indentation of `file`.
+    pub(crate) fn resolve(self, file: &str) -> Self {
+        if self != Self::Infer {
+            return self;
+        }
+
+        file.lines()
+            .find_map(|line| {
+                let content = line.trim_start();
+                let indent = &line[..line.len() - content.len()];
+                // skip the continuation lines of block comments, which are usually indented by
+                // a single space
+                (!indent.is_empty() && !content.is_empty() && !content.starts_with('*')).then(
+                    || {
+                        if indent.starts_with('\t') {
+                            Self::Tabs
+                        } else {
+                            Self::Spaces(indent.len())
+                        }
+                    },
+                )
+            })
+            .unwrap_or(Self::Spaces(4))
+    }
+
+    /// The text of a single indentation level.
+    pub(crate) fn unit(self) -> String {
+        match self {
+            Self::Spaces(n) => " ".repeat(n),
+            Self::Tabs => "\t".to_owned(),
+            Self::Infer => Self::Spaces(4).unit(),
+        }
+    }
+
+    /// The width of a single indentation level, counting tabs as 4 columns (like rustfmt's
+    /// default `tab_spaces`).
+    pub(crate) fn width(self) -> usize {
+        match self {
+            Self::Spaces(n) => n,
+            Self::Tabs | Self::Infer => 4,
+        }
+    }
+}
+
+/// How imports are split into blocks, mirroring rustfmt's `group_imports`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum GroupImports {
+    /// One block per [`Category`], in [`FormatOptions::category_order`].
+    #[default]
+    StdExternalCrate,
+    /// A single sorted block containing all imports.
+    One,
+    /// Keep the existing blocks (separated by blank lines) in their original order, sorting and
+    /// merging the imports within each block only. Without the source text to find blocks in
+    /// (e.g. in [`format_items`](crate::format_items)), all imports form a single block.
+    Preserve,
+}
+
+/// How idents are compared when sorting imports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum SortMode {
+    /// Byte-wise, so uppercase letters sort before lowercase ones (e.g. `HTTPServer` before
+    /// `HttpClient`, and `Zeta` before `alpha`).
+    #[default]
+    Ascii,
+    /// Case-insensitively, word by word. Idents are split into words at underscores, before an
+    /// uppercase letter following a lowercase letter or a digit, and before the last uppercase
+    /// letter of a run if a lowercase letter follows it (so `HTTPServer` is `HTTP` and `Server`).
+    /// Idents are compared by their lowercased words, breaking ties byte-wise, so that e.g.
+    /// `HttpClient` sorts before `HTTPServer`, `HttpServer` and `http_server` (in that order).
+    SmartCase,
+    /// Like [`Ascii`](Self::Ascii), but the statements of each block are first ordered by the
+    /// depth of their paths, so that e.g. `use std::mem;` comes before
+    /// `use serde::de::Deserialize;`. A merged statement is as deep as its shallowest import
+    /// (`std::{mem, collections::HashMap}` is as deep as `std::mem`), so this mostly matters
+    /// along with options keeping statements separate, e.g.
+    /// [`merge_pub_use`](FormatOptions::merge_pub_use).
+    ByDepthThenName,
+}
+
+impl SortMode {
+    pub(crate) fn cmp_idents(self, ident: &Ident, other: &Ident) -> Ordering {
+        match self {
+            Self::Ascii | Self::ByDepthThenName => ident.cmp(other),
+            Self::SmartCase => words(&ident.to_string())
+                .cmp(&words(&other.to_string()))
+                .then_with(|| ident.cmp(other)),
+        }
+    }
+}
+
+/// How the roots of imports in the [`Crate`](Category::Crate) category are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum CrateOrder {
+    /// Like any other roots, by [`FormatOptions::sort_mode`] (so `crate`, `self`, `super`).
+    #[default]
+    Alphabetical,
+    /// `crate`, `super` and `self` (in that order) before any other roots, e.g. ones a
+    /// [`Classifier`] puts in the crate category. Since statements are sorted before they're
+    /// grouped, with [`GroupImports::One`] this puts them before std and external crates too.
+    KeywordsFirst,
+}
+
+/// Splits an ident into lowercased words, for [`SortMode::SmartCase`].
+fn words(ident: &str) -> Vec<String> {
+    let chars = ident.chars().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            words.extend((!word.is_empty()).then(|| mem::take(&mut word)));
+            continue;
+        }
+
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let starts_word = c.is_uppercase()
+            && match prev {
+                Some(prev) if prev.is_lowercase() || prev.is_numeric() => true,
+                Some(prev) if prev.is_uppercase() => next.is_some_and(|next| next.is_lowercase()),
+                _ => false,
+            };
+        if starts_word && !word.is_empty() {
+            words.push(mem::take(&mut word));
+        }
+        word.extend(c.to_lowercase());
+    }
+    words.extend((!word.is_empty()).then_some(word));
+
+    words
+}
+
+type CmpFn = dyn Fn(&UseKey, &UseKey) -> Ordering + Send + Sync;
+
+/// A custom order for imports, overriding the built-in one (see [`FormatOptions::comparator`]).
+#[derive(Clone)]
+pub struct Comparator(Arc<CmpFn>);
+
+impl Comparator {
+    pub fn new(cmp: impl Fn(&UseKey, &UseKey) -> Ordering + Send + Sync + 'static) -> Self {
+        Self(Arc::new(cmp))
+    }
+
+    pub(crate) fn cmp(&self, key: &UseKey, other: &UseKey) -> Ordering {
+        (self.0)(key, other)
+    }
+}
+
+impl fmt::Debug for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Comparator").finish_non_exhaustive()
+    }
+}
+
+type ClassifyFn = dyn Fn(&[&str]) -> Category + Send + Sync;
+
+/// A custom categorization of imports, overriding the built-in one (see
+/// [`FormatOptions::classifier`]).
+///
+/// It's called with the path of each import, as a list of segments: e.g. `["crate", "a", "B"]`
+/// for `crate::a::B`, whether it's imported as part of a group or not. A leading `::` is an empty
+/// first segment, a glob import's last segment is `*`, and a renamed import's is its original
+/// name.
+#[derive(Clone)]
+pub struct Classifier(Arc<ClassifyFn>);
+
+impl Classifier {
+    pub fn new(classify: impl Fn(&[&str]) -> Category + Send + Sync + 'static) -> Self {
+        Self(Arc::new(classify))
+    }
+
+    pub(crate) fn classify(&self, path: &[&str]) -> Category {
+        (self.0)(path)
+    }
+}
+
+impl fmt::Debug for Classifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Classifier").finish_non_exhaustive()
+    }
+}
+
+type MatchFn = dyn Fn(&[&str]) -> bool + Send + Sync;
+
+/// Which imports a category of a [`CategorySet`] contains.
+///
+/// Paths are matched as lists of segments, like by a [`Classifier`]. Other than the built-in
+/// categorization, matchers ignore a leading `::`.
+#[derive(Clone)]
+pub enum CategoryMatcher {
+    /// The imports the built-in categorization ([`Category::of_path`]) puts in the given
+    /// category.
+    Builtin(Category),
+    /// Imports whose first segment is one of the given ones, e.g. crate names, or `crate`, `self`
+    /// and `super`.
+    Roots(BTreeSet<String>),
+    /// Imports whose path starts with the given segments, e.g. `["crate", "test_utils"]`.
+    Prefix(Vec<String>),
+    /// The imports of `pub use` re-exports (but not of restricted ones, e.g. `pub(crate) use`).
+    Public,
+    /// The imports the function returns `true` for.
+    Custom(Arc<MatchFn>),
+}
+
+impl CategoryMatcher {
+    /// Matches the imports the function returns `true` for.
+    pub fn custom(matches: impl Fn(&[&str]) -> bool + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(matches))
+    }
+
+    fn matches(&self, path: &[&str], vis: &Visibility, opts: &FormatOptions) -> bool {
+        let segments = match path {
+            ["", path @ ..] => path,
+            path => path,
+        };
+        match self {
+            Self::Builtin(category) => Category::of_path(path, opts) == *category,
+            Self::Roots(roots) => segments.first().is_some_and(|root| roots.contains(*root)),
+            Self::Prefix(prefix) => {
+                segments.len() >= prefix.len() && segments.iter().zip(prefix).all(|(a, b)| a == b)
+            }
+            Self::Public => matches!(vis, Visibility::Public(_)),
+            Self::Custom(matches) => matches(path),
+        }
+    }
+}
+
+impl fmt::Debug for CategoryMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Builtin(category) => f.debug_tuple("Builtin").field(category).finish(),
+            Self::Roots(roots) => f.debug_tuple("Roots").field(roots).finish(),
+            Self::Prefix(prefix) => f.debug_tuple("Prefix").field(prefix).finish(),
+            Self::Public => f.write_str("Public"),
+            Self::Custom(_) => f.debug_tuple("Custom").finish_non_exhaustive(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CategoryEntry {
+    category: Category,
+    matcher: CategoryMatcher,
+    blank_line_before: bool,
+}
+
+/// An ordered list of categories, each with the imports it contains and whether its block is
+/// separated from the previous one by a blank line, see [`FormatOptions::categories`].
+///
+/// Each import goes in the first category that matches it, or if none does, in the category the
+/// built-in categorization puts it in (emitted after the listed ones). The default set has the
+/// built-in categories, in their default order.
+///
+/// ```
+/// use user_spray::{Category, CategoryMatcher, CategorySet};
+///
+/// let categories = CategorySet::new()
+///     .with(Category::Std, CategoryMatcher::Builtin(Category::Std))
+///     .with(
+///         Category::Custom("tests"),
+///         CategoryMatcher::Prefix(vec!["crate".into(), "tests".into()]),
+///     )
+///     .with(Category::External, CategoryMatcher::Builtin(Category::External))
+///     .with(Category::Crate, CategoryMatcher::Builtin(Category::Crate))
+///     .without_blank_line();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CategorySet {
+    entries: Vec<CategoryEntry>,
+}
+
+impl CategorySet {
+    /// An empty set, putting every import in its built-in category.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds `category` after the others, containing the imports `matcher` matches (and no
+    /// earlier category does). If `category` was already added, it also contains these imports,
+    /// but its block stays where it was.
+    pub fn with(mut self, category: Category, matcher: CategoryMatcher) -> Self {
+        self.entries.push(CategoryEntry {
+            category,
+            matcher,
+            blank_line_before: true,
+        });
+        self
+    }
+
+    /// Emits the block of the category added last right below the previous block, without a
+    /// blank line between them.
+    pub fn without_blank_line(mut self) -> Self {
+        if let Some(entry) = self.entries.last_mut() {
+            entry.blank_line_before = false;
+        }
+        self
+    }
+
+    pub(crate) fn classify(
+        &self,
+        path: &[&str],
+        vis: &Visibility,
+        opts: &FormatOptions,
+    ) -> Category {
+        self.entries
+            .iter()
+            .find(|entry| entry.matcher.matches(path, vis, opts))
+            .map_or_else(|| Category::of_path(path, opts), |entry| entry.category)
+    }
+
+    fn categories(&self) -> impl Iterator<Item = Category> + '_ {
+        self.entries.iter().map(|entry| entry.category)
+    }
+
+    fn blank_line_before(&self, category: Category) -> bool {
+        self.entries
+            .iter()
+            .find(|entry| entry.category == category)
+            .is_none_or(|entry| entry.blank_line_before)
+    }
+}
+
+impl Default for CategorySet {
+    fn default() -> Self {
+        Category::ALL
+            .into_iter()
+            .fold(Self::new(), |set, category| {
+                set.with(category, CategoryMatcher::Builtin(category))
+            })
+    }
+}
+
+/// Options controlling how imports are grouped, sorted and merged.
+///
+/// With the `serde` feature, this can be deserialized (e.g. from a config file). Missing fields
+/// take their default values, and unknown fields are rejected.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct FormatOptions {
+    /// Sort renamed imports (`use foo as bar;`, or `bar as baz` in a group) by their alias rather
+    /// than by the original name they import.
+    pub sort_renames_by_alias: bool,
+    /// How idents are compared when sorting statements and the members of groups.
+    pub sort_mode: SortMode,
+    /// How `crate`, `self` and `super` are ordered relative to each other (and to other roots).
+    pub crate_order: CrateOrder,
+    /// Additional crate names to group along with `std`, `core` and `alloc` (e.g. `sp_std`).
+    pub std_crates: BTreeSet<String>,
+    /// Leave a run of `use` items byte-for-byte untouched if formatting it would only change
+    /// whitespace, to avoid churn (e.g. when it was already wrapped by rustfmt).
+    pub preserve_unchanged: bool,
+    /// The number of blank lines between the file's docs (and inner attributes, e.g.
+    /// `#![allow(..)]`) and its imports, when those are its first items. The existing spacing is
+    /// kept if `None`.
+    pub blank_after_module_docs: Option<usize>,
+    /// Normalize the blank lines between the file's last run of imports and the code following
+    /// it to a single one. Otherwise, they're left as they are. A file ending with imports always
+    /// ends with exactly one newline.
+    pub normalize_trailing_blank_lines: bool,
+    /// The maximum line width. Statements wider than this have their groups broken over
+    /// multiple lines, the way rustfmt does.
+    pub max_width: usize,
+    /// The indentation of wrapped group members.
+    pub indent: Indent,
+    /// Keep every statement on a single line: statements wider than [`max_width`] are not
+    /// wrapped, and are marked with `#[rustfmt::skip]` so rustfmt doesn't wrap them either.
+    ///
+    /// Such markers are regenerated when formatting again, so a `#[rustfmt::skip]` on a statement
+    /// too wide to fit is taken for one. On any other statement, it still leaves its run
+    /// untouched.
+    ///
+    /// [`max_width`]: Self::max_width
+    pub always_single_line: bool,
+    /// Drop named imports made redundant by a glob import of the same module, e.g. `Bar` in
+    /// `use foo::{Bar, *};`.
+    pub drop_glob_covered: bool,
+    /// Also format runs of `use` items in function bodies (free functions, methods, and default
+    /// methods of traits, including ones in inline modules or nested in other functions), not
+    /// just top-level ones. They are otherwise left untouched, and so are runs in blocks within
+    /// expressions (e.g. an `if`'s body).
+    pub sort_block_imports: bool,
+    /// Also format runs of `use` items in inline modules (`mod foo { ... }`, including nested
+    /// ones), indented to the module's level. Each module's imports are sorted and merged on their
+    /// own, never with those of other modules.
+    pub sort_module_imports: bool,
+    /// Only sort statements into blocks, keeping the original text of each (including any
+    /// comments and formatting) instead of merging them.
+    pub reorder_only: bool,
+    /// Keep comments found between `use` items, placing each above the statement that the
+    /// following item is merged into (or, for a comment trailing an item on the same line, above
+    /// that item's statement).
+    pub preserve_comments: bool,
+    /// Separate the external block into sub-blocks, one per crate, with a blank line between
+    /// them.
+    pub split_external_crates: bool,
+    /// Separate the `pub use` re-exports of each block from its other imports (including
+    /// restricted ones, e.g. `pub(crate) use`) with a blank line, moving them after the rest.
+    pub separate_pub_use: bool,
+    /// Emit renamed imports (`Bar as Baz`) as standalone statements, rather than merging them
+    /// into groups along with the rest of their module's imports.
+    pub standalone_renames: bool,
+    /// Merge `pub use` re-exports like other imports. Otherwise, each re-exported item gets a
+    /// statement of its own (e.g. `pub use foo::Bar;` and `pub use foo::Baz;`), so that changes
+    /// to a crate's public API stand out in diffs.
+    pub merge_pub_use: bool,
+    /// Treat a single-line `//` comment right above a block of imports (at the start of a run, or
+    /// after a blank line) as the header of the category of the import below it, and emit it
+    /// above that category's block. Only applies to [`GroupImports::StdExternalCrate`].
+    ///
+    /// Otherwise, a comment above a run is left in place (so it ends up above whichever block
+    /// comes first), and comments within a run are treated like any other (see
+    /// [`preserve_comments`]).
+    ///
+    /// [`preserve_comments`]: Self::preserve_comments
+    pub category_headers: bool,
+    /// Merge imports of a module and of its members into a single statement, importing the
+    /// module as `self` (e.g. `use foo::{self, bar};`). Otherwise, the module is imported by a
+    /// statement of its own (`use foo;` and `use foo::{bar};`), including when the input already
+    /// imports it as `self` in a group.
+    pub collapse_self: bool,
+    /// Emit groups of a single member without braces, e.g. `use a::b::c;` rather than
+    /// `use a::{b::{c}};` (as rustfmt does). Groups of just `self` are kept (see
+    /// [`drop_lone_self`](Self::drop_lone_self)).
+    pub collapse_single_groups: bool,
+    /// Import modules imported as nothing but `self` directly, e.g. `use foo;` rather than
+    /// `use foo::{self};`. This isn't the default, since `foo::{self}` only imports `foo` from the
+    /// type namespace, while `foo` would also import a function or value of the same name.
+    pub drop_lone_self: bool,
+    /// Pad the visibility of statements in blocks mixing visibilities, so that their `use`
+    /// keywords (and paths) line up, e.g. `pub use foo;` above `    use bar;`. rustfmt undoes
+    /// this, so it's only useful along with `--skip-rustfmt`.
+    pub align_visibility: bool,
+    /// How imports are split into blocks.
+    pub group_imports: GroupImports,
+    /// The order category blocks are emitted in.
+    ///
+    /// When deserialized or parsed, this must list every category exactly once. If set directly,
+    /// duplicates are ignored, and any category missing from the list is emitted after the listed
+    /// ones, in the default order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::de::category_order")
+    )]
+    pub category_order: Vec<Category>,
+    /// Order statements, and the members of their groups, with a custom comparator instead of
+    /// the built-in order ([`UseKey::cmp_with`], which it can delegate to).
+    ///
+    /// Group members are compared as keys sharing their statement's attributes and visibility.
+    /// Keys (and members) the comparator considers equal are ordered by the built-in order, so
+    /// that only identical keys are merged into the same statement.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub comparator: Option<Comparator>,
+    /// Categorize imports with a custom classifier instead of the built-in categorization
+    /// ([`Category::of_path`], which it can delegate to), e.g. to give some module's imports a
+    /// block of their own with a [`Category::Custom`].
+    ///
+    /// Imports of the same statement that are categorized differently are split into statements
+    /// of their own (except in [`reorder_only`](Self::reorder_only) mode, where statements are
+    /// categorized by their first import).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub classifier: Option<Classifier>,
+    /// Categorize imports, and order the categories' blocks, by a [`CategorySet`] instead. This
+    /// overrides both [`classifier`](Self::classifier) and
+    /// [`category_order`](Self::category_order).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub categories: Option<CategorySet>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            sort_renames_by_alias: false,
+            sort_mode: SortMode::default(),
+            crate_order: CrateOrder::default(),
+            std_crates: BTreeSet::new(),
+            preserve_unchanged: false,
+            blank_after_module_docs: None,
+            normalize_trailing_blank_lines: true,
+            // rustfmt's default
+            max_width: 100,
+            indent: Indent::default(),
+            always_single_line: false,
+            drop_glob_covered: false,
+            sort_block_imports: false,
+            sort_module_imports: false,
+            reorder_only: false,
+            preserve_comments: false,
+            split_external_crates: false,
+            separate_pub_use: false,
+            standalone_renames: false,
+            merge_pub_use: true,
+            category_headers: false,
+            collapse_self: true,
+            collapse_single_groups: false,
+            drop_lone_self: false,
+            align_visibility: false,
+            group_imports: GroupImports::default(),
+            category_order: Category::ALL.to_vec(),
+            comparator: None,
+            classifier: None,
+            categories: None,
+        }
+    }
+}
+
+impl FormatOptions {
+    pub(crate) fn keeps_category_headers(&self) -> bool {
+        self.category_headers && self.group_imports == GroupImports::StdExternalCrate
+    }
+
+    /// Whether the block of `category` is separated from the previous block by a blank line.
+    pub(crate) fn blank_line_before(&self, category: Category) -> bool {
+        self.categories
+            .as_ref()
+            .is_none_or(|set| set.blank_line_before(category))
+    }
+
+    /// Compares keys with the [`comparator`](Self::comparator), if any, breaking ties with the
+    /// built-in order.
+    pub(crate) fn cmp_keys(&self, key: &UseKey, other: &UseKey) -> Ordering {
+        self.comparator
+            .as_ref()
+            .map_or(Ordering::Equal, |comparator| comparator.cmp(key, other))
+            .then_with(|| key.cmp_with(other, self))
+    }
+
+    /// The order category blocks are emitted in, by the [`categories`](Self::categories) if set,
+    /// and otherwise by [`category_order`](Self::category_order).
+    pub(crate) fn category_order(&self) -> impl Iterator<Item = Category> + '_ {
+        let listed = match &self.categories {
+            Some(set) => set.categories().collect(),
+            None => self.category_order.clone(),
+        };
+        let mut seen = Vec::new();
+        listed
+            .into_iter()
+            .chain(Category::ALL)
+            .filter(move |category| {
+                let first = !seen.contains(category);
+                seen.push(*category);
+                first
+            })
+    }
+}
+
+/// The error returned when parsing an invalid `KEY=VALUE` option (see [`FormatOptions::set`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseOptionsError {
+    /// The option isn't of the form `KEY=VALUE`.
+    Syntax(String),
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ParseOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(s) => write!(f, "expected `KEY=VALUE`, got `{s}`"),
+            Self::UnknownKey(key) => write!(f, "unknown config key `{key}`"),
+            Self::InvalidValue {
+                key,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value `{value}` for `{key}` (expected {expected})"
+            ),
+        }
+    }
+}
+
+impl Error for ParseOptionsError {}
+
+impl FormatOptions {
+    /// Sets the field named `key` from its textual `value`, the way rustfmt's `--config` flag
+    /// does. Lists (`std_crates` and `category_order`) are comma-separated.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ParseOptionsError> {
+        let (key, value) = (key.trim(), value.trim());
+
+        let invalid = |expected| ParseOptionsError::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            expected,
+        };
+        let bool = || value.parse().map_err(|_| invalid("`true` or `false`"));
+        let list = || {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+        };
+
+        match key {
+            "sort_renames_by_alias" => self.sort_renames_by_alias = bool()?,
+            "sort_mode" => {
+                self.sort_mode = match value {
+                    "Ascii" => SortMode::Ascii,
+                    "SmartCase" => SortMode::SmartCase,
+                    "ByDepthThenName" => SortMode::ByDepthThenName,
+                    _ => return Err(invalid("`Ascii`, `SmartCase` or `ByDepthThenName`")),
+                }
+            }
+            "crate_order" => {
+                self.crate_order = match value {
+                    "Alphabetical" => CrateOrder::Alphabetical,
+                    "KeywordsFirst" => CrateOrder::KeywordsFirst,
+                    _ => return Err(invalid("`Alphabetical` or `KeywordsFirst`")),
+                }
+            }
+            "std_crates" => self.std_crates = list().map(str::to_owned).collect(),
+            "preserve_unchanged" => self.preserve_unchanged = bool()?,
+            "blank_after_module_docs" => {
+                self.blank_after_module_docs = match value {
+                    "none" => None,
+                    _ => Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid("`none` or a number of blank lines"))?,
+                    ),
+                }
+            }
+            "normalize_trailing_blank_lines" => self.normalize_trailing_blank_lines = bool()?,
+            "max_width" => self.max_width = value.parse().map_err(|_| invalid("a number"))?,
+            "indent" => {
+                self.indent = match value {
+                    "tabs" => Indent::Tabs,
+                    "infer" => Indent::Infer,
+                    _ => Indent::Spaces(
+                        value
+                            .parse()
+                            .map_err(|_| invalid("`tabs`, `infer` or a number of spaces"))?,
+                    ),
+                }
+            }
+            "always_single_line" => self.always_single_line = bool()?,
+            "drop_glob_covered" => self.drop_glob_covered = bool()?,
+            "sort_block_imports" => self.sort_block_imports = bool()?,
+            "sort_module_imports" => self.sort_module_imports = bool()?,
+            "reorder_only" => self.reorder_only = bool()?,
+            "preserve_comments" => self.preserve_comments = bool()?,
+            "split_external_crates" => self.split_external_crates = bool()?,
+            "separate_pub_use" => self.separate_pub_use = bool()?,
+            "standalone_renames" => self.standalone_renames = bool()?,
+            "merge_pub_use" => self.merge_pub_use = bool()?,
+            "category_headers" => self.category_headers = bool()?,
+            "collapse_self" => self.collapse_self = bool()?,
+            "collapse_single_groups" => self.collapse_single_groups = bool()?,
+            "drop_lone_self" => self.drop_lone_self = bool()?,
+            "align_visibility" => self.align_visibility = bool()?,
+            "group_imports" => {
+                self.group_imports = match value {
+                    "StdExternalCrate" => GroupImports::StdExternalCrate,
+                    "One" => GroupImports::One,
+                    "Preserve" => GroupImports::Preserve,
+                    _ => return Err(invalid("`StdExternalCrate`, `One` or `Preserve`")),
+                }
+            }
+            "category_order" => {
+                let invalid = || {
+                    invalid(
+                        "a comma-separated list of `std`, `external` and `crate`, each exactly \
+                         once",
+                    )
+                };
+                let order = list()
+                    .map(str::parse)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| invalid())?;
+                Category::check_order(&order).map_err(|_| invalid())?;
+                self.category_order = order;
+            }
+            _ => return Err(ParseOptionsError::UnknownKey(key.to_owned())),
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses options from `KEY=VALUE` lines (see [`FormatOptions::set`]), starting from the
+/// defaults. Blank lines and lines starting with `#` are ignored.
+impl FromStr for FormatOptions {
+    type Err = ParseOptionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut opts = Self::default();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| ParseOptionsError::Syntax(line.to_owned()))?;
+            opts.set(key, value)?;
+        }
+        Ok(opts)
+    }
+}
@@ -1,93 +1,184 @@
 use std::{
-    fmt,
-    io::{self, stdout, StdoutLock, Write},
-    mem::ManuallyDrop,
-    process::{self, Command, Stdio},
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Output, Stdio},
+    thread,
 };
 
 use fn_formats::DisplayFmt;
+use syn::Item;
 
-use crate::Args;
+/// Flags changing what rustfmt outputs, which inputs can't be batched with (see
+/// [`Rustfmt::format_all`]).
+const OUTPUT_FLAGS: &[&str] = &["--emit", "--check", "-l", "--files-with-diff"];
 
-pub(super) enum Output {
-    Stdout(StdoutLock<'static>),
-    Rustfmt {
-        process: process::Child,
-        stdin: ManuallyDrop<process::ChildStdin>,
-    },
+/// Runs rustfmt (with the arguments passed after `--`), either on single inputs or on many at
+/// once.
+pub(super) struct Rustfmt {
+    args: Vec<String>,
+    /// Only pass rustfmt's stderr on when it fails, hiding its warnings.
+    quiet: bool,
 }
 
-impl Drop for Output {
-    fn drop(&mut self) {
-        if let Self::Rustfmt { process, stdin } = self {
-            // Safety: stdin will never be accessed again
-            unsafe { ManuallyDrop::drop(stdin) };
-
-            let status = process.wait().expect("Could not wait for rustfmt to exit");
-            if !status.success() {
-                panic!(
-                    "Rustfmt exited unsuccessfully{}",
-                    DisplayFmt(|f| {
-                        if let Some(code) = status.code() {
-                            write!(f, " (code: {code})")?;
-                        }
-                        Ok(())
-                    })
-                );
-            }
+impl Rustfmt {
+    pub(super) fn new(args: &[String], quiet: bool) -> Self {
+        Self {
+            args: args.to_vec(),
+            quiet,
         }
     }
-}
 
-impl Output {
-    pub(super) fn new(
-        Args {
-            skip_rustfmt,
-            rustfmt_args,
-        }: Args,
-    ) -> io::Result<Self> {
-        Ok(if skip_rustfmt {
-            Self::Stdout(stdout().lock())
+    fn stderr(&self) -> Stdio {
+        if self.quiet {
+            Stdio::piped()
         } else {
-            let mut rustfmt = Command::new("rustfmt")
-                .args(rustfmt_args)
-                .stdin(Stdio::piped())
-                .spawn()?;
-            let stdin = rustfmt.stdin.take().unwrap();
-            Self::Rustfmt {
-                process: rustfmt,
-                stdin: ManuallyDrop::new(stdin),
-            }
-        })
+            Stdio::inherit()
+        }
     }
-}
 
-impl Write for Output {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self {
-            Output::Stdout(stdout) => stdout.write(buf),
-            Output::Rustfmt { stdin, .. } => stdin.write(buf),
-        }
+    /// Runs `input` through rustfmt, returning its output.
+    pub(super) fn format(&self, input: &str) -> io::Result<String> {
+        let mut rustfmt = Command::new("rustfmt")
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(self.stderr())
+            .spawn()?;
+
+        // write from another thread, so rustfmt can't block on a full stdout pipe while we're
+        // blocked on writing its stdin
+        let mut stdin = rustfmt.stdin.take().unwrap();
+        let input = input.to_owned();
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let output = rustfmt.wait_with_output()?;
+        writer.join().unwrap()?;
+
+        check_output(&output)?;
+
+        String::from_utf8(output.stdout).map_err(io::Error::other)
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        match self {
-            Output::Stdout(stdout) => stdout.flush(),
-            Output::Rustfmt { stdin, .. } => stdin.flush(),
+    /// Runs each of `inputs` through rustfmt, like [`format`](Self::format), but with a single
+    /// rustfmt process for as many of them as possible, returning their outputs in order.
+    ///
+    /// rustfmt can only format a single input from stdin, so the inputs are written to files in
+    /// a temporary directory instead, with the config file rustfmt would find for stdin passed
+    /// explicitly. Inputs with out-of-line modules are formatted one by one, since rustfmt would
+    /// look for their modules next to the temporary files. Nothing is batched if rustfmt's
+    /// arguments change what it outputs (e.g. `--check`), and if the batch fails, each of its
+    /// inputs is formatted on its own, to tell which ones failed.
+    pub(super) fn format_all(&self, inputs: &[&str]) -> Vec<io::Result<String>> {
+        let mut outputs = inputs.iter().map(|_| None).collect::<Vec<_>>();
+
+        let batch = (0..inputs.len())
+            .filter(|&i| !has_out_of_line_modules(inputs[i]))
+            .collect::<Vec<_>>();
+        let batchable = !self.args.iter().any(|arg| {
+            OUTPUT_FLAGS
+                .iter()
+                .any(|flag| arg == flag || arg.starts_with(&format!("{flag}=")))
+        });
+        if batchable && batch.len() > 1 {
+            let dir = env::temp_dir().join(format!("user-spray-{}", process::id()));
+            let batched = self.format_files(&dir, &batch, inputs);
+            let _ = fs::remove_dir_all(&dir);
+            if let Ok(batched) = batched {
+                for (i, output) in batch.into_iter().zip(batched) {
+                    outputs[i] = Some(Ok(output));
+                }
+            }
         }
+
+        outputs
+            .into_iter()
+            .zip(inputs)
+            .map(|(output, input)| output.unwrap_or_else(|| self.format(input)))
+            .collect()
     }
 
-    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        match self {
-            Output::Stdout(stdout) => stdout.write_all(buf),
-            Output::Rustfmt { stdin, .. } => stdin.write_all(buf),
+    /// Writes the `batch` of `inputs` to files in `dir`, formats them in place with a single
+    /// rustfmt process, and reads them back.
+    fn format_files(
+        &self,
+        dir: &Path,
+        batch: &[usize],
+        inputs: &[&str],
+    ) -> io::Result<Vec<String>> {
+        fs::create_dir(dir)?;
+        let paths = batch
+            .iter()
+            .map(|&i| {
+                let path = dir.join(format!("{i}.rs"));
+                fs::write(&path, inputs[i])?;
+                Ok(path)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut rustfmt = Command::new("rustfmt");
+        rustfmt.args(&self.args);
+        if !self.args.iter().any(|arg| arg.starts_with("--config-path")) {
+            if let Some(config) = find_config()? {
+                rustfmt.arg("--config-path").arg(config);
+            }
         }
+        // the files are formatted in place, so anything rustfmt prints (e.g. with `-v`) is only
+        // noise in our own output
+        let output = rustfmt
+            .args(&paths)
+            .stdout(Stdio::null())
+            .stderr(self.stderr())
+            .output()?;
+        check_output(&output)?;
+
+        paths.iter().map(fs::read_to_string).collect()
     }
+}
 
-    fn write_fmt(&mut self, fmt: fmt::Arguments<'_>) -> io::Result<()> {
-        match self {
-            Output::Stdout(stdout) => stdout.write_fmt(fmt),
-            Output::Rustfmt { stdin, .. } => stdin.write_fmt(fmt),
-        }
+/// Finds the config file rustfmt uses when formatting stdin: the first `rustfmt.toml` (or
+/// `.rustfmt.toml`) in the current directory or any of its ancestors.
+fn find_config() -> io::Result<Option<PathBuf>> {
+    let dir = env::current_dir()?;
+    Ok(dir
+        .ancestors()
+        .flat_map(|dir| ["rustfmt.toml", ".rustfmt.toml"].map(|name| dir.join(name)))
+        .find(|path| path.is_file()))
+}
+
+/// Whether `input` declares any modules whose contents live in other files (e.g. `mod foo;`),
+/// which rustfmt looks for relative to the file being formatted.
+fn has_out_of_line_modules(input: &str) -> bool {
+    fn any_out_of_line(items: &[Item]) -> bool {
+        items.iter().any(|item| match item {
+            Item::Mod(item) => item
+                .content
+                .as_ref()
+                .is_none_or(|(_, items)| any_out_of_line(items)),
+            _ => false,
+        })
     }
+
+    // rustfmt would fail on input that doesn't parse, which is better found out on its own
+    syn::parse_file(input).map_or(true, |file| any_out_of_line(&file.items))
+}
+
+/// Checks that rustfmt succeeded, passing on its stderr if it was captured (see
+/// [`Rustfmt::quiet`]) and it failed.
+fn check_output(output: &Output) -> io::Result<()> {
+    let status = output.status;
+    if status.success() {
+        return Ok(());
+    }
+    io::stderr().write_all(&output.stderr)?;
+
+    Err(io::Error::other(format!(
+        "rustfmt exited unsuccessfully{}",
+        DisplayFmt(|f| {
+            if let Some(code) = status.code() {
+                write!(f, " (code: {code})")?;
+            }
+            Ok(())
+        })
+    )))
 }
@@ -0,0 +1,83 @@
+use syn::{Block, ImplItem, Item, ItemMod, ItemUse, Stmt, TraitItem};
+
+/// A scope `use` items can appear in.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Scope<'a> {
+    /// The items of a file, or of an inline module (`mod foo { ... }`).
+    Module(&'a [Item]),
+    /// The statements of a function's body.
+    Body(&'a Block),
+}
+
+impl<'a> Scope<'a> {
+    /// The scope's `use` items, with `None` standing for any other item or statement (see
+    /// [`use_runs`](crate::use_runs)).
+    pub(crate) fn use_items(self) -> Vec<Option<&'a ItemUse>> {
+        self.items()
+            .into_iter()
+            .map(|item| match item {
+                Some(Item::Use(item)) => Some(item),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The scope's items, with `None` standing for statements that aren't items.
+    fn items(self) -> Vec<Option<&'a Item>> {
+        match self {
+            Self::Module(items) => items.iter().map(Some).collect(),
+            Self::Body(block) => block
+                .stmts
+                .iter()
+                .map(|stmt| match stmt {
+                    Stmt::Item(item) => Some(item),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Finds the scopes nested in `items` (e.g. a file's), at any depth: inline modules, and the
+/// bodies of functions, whether free ones, methods in `impl` blocks or default methods of traits.
+/// Items in function bodies are searched too (e.g. a function nested in another), but blocks
+/// within expressions (e.g. an `if`'s) aren't, and neither are their items.
+pub(crate) fn nested_scopes(items: &[Item]) -> Vec<Scope<'_>> {
+    let mut scopes = Vec::new();
+    collect(items.iter(), &mut scopes);
+    scopes
+}
+
+fn collect<'a>(items: impl IntoIterator<Item = &'a Item>, scopes: &mut Vec<Scope<'a>>) {
+    for item in items {
+        let nested = match item {
+            Item::Mod(ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => vec![Scope::Module(items)],
+            Item::Fn(item) => vec![Scope::Body(&item.block)],
+            Item::Impl(item) => item
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    ImplItem::Fn(item) => Some(Scope::Body(&item.block)),
+                    _ => None,
+                })
+                .collect(),
+            Item::Trait(item) => item
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    TraitItem::Fn(item) => item.default.as_ref().map(Scope::Body),
+                    _ => None,
+                })
+                .collect(),
+            _ => continue,
+        };
+
+        for scope in nested {
+            scopes.push(scope);
+            collect(scope.items().into_iter().flatten(), scopes);
+        }
+    }
+}
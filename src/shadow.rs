@@ -0,0 +1,76 @@
+use std::{collections::HashMap, fmt, iter};
+
+use syn::{spanned::Spanned, Item};
+
+use crate::{
+    scope::{self, Scope},
+    tree,
+};
+
+/// An import binding the same name as an earlier import in the same scope, but to a different
+/// path (e.g. `use b::Error;` after `use a::Error;`), see
+/// [`shadowed_imports`](crate::shadowed_imports).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowing {
+    /// The name both imports bind.
+    pub name: String,
+    /// The path of the earlier import, and the line it's on.
+    pub first: (String, usize),
+    /// The path of the later import, and the line it's on.
+    pub second: (String, usize),
+}
+
+impl fmt::Display for Shadowing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            name,
+            first: (first_path, first_line),
+            second: (second_path, second_line),
+        } = self;
+        write!(
+            f,
+            "line {second_line}: `{name}` is imported from `{second_path}`, but was already \
+             imported from `{first_path}` (line {first_line})"
+        )
+    }
+}
+
+/// Finds the shadowed imports of each scope in `items` (see
+/// [`shadowed_imports`](crate::shadowed_imports)), in the order of the scopes and then of the
+/// shadowing imports.
+pub(crate) fn find(items: &[Item]) -> Vec<Shadowing> {
+    let scopes = iter::once(Scope::Module(items)).chain(scope::nested_scopes(items));
+
+    let mut shadowings = Vec::new();
+    for scope in scopes {
+        let mut bound = HashMap::<String, (String, usize)>::new();
+        for item in scope.use_items().into_iter().flatten() {
+            if item.attrs.iter().any(|attr| attr.path().is_ident("cfg")) {
+                continue;
+            }
+            let leading_colon = if item.leading_colon.is_some() {
+                "::"
+            } else {
+                ""
+            };
+            let line = item.span().start().line;
+
+            for (path, name) in tree::bindings(item.tree.clone()) {
+                let path = format!("{leading_colon}{path}");
+                match bound.get(&name) {
+                    Some(first) if first.0 != path => shadowings.push(Shadowing {
+                        name,
+                        first: first.clone(),
+                        second: (path, line),
+                    }),
+                    Some(_) => {}
+                    None => {
+                        bound.insert(name, (path, line));
+                    }
+                }
+            }
+        }
+    }
+
+    shadowings
+}
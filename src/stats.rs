@@ -0,0 +1,49 @@
+use std::{collections::HashMap, fmt};
+
+use crate::Category;
+
+/// Counts describing the imports of a formatted file, see [`stats`](crate::stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatStats {
+    /// The number of `use` statements in the input.
+    pub parsed: usize,
+    /// The number of `use` statements in the output.
+    pub emitted: usize,
+    /// The number of output statements in each category.
+    pub categories: HashMap<Category, usize>,
+    /// The paths of the imports dropped for being covered by a glob import (see
+    /// [`drop_glob_covered`](crate::FormatOptions::drop_glob_covered)), e.g. `foo::Bar` in
+    /// `use foo::{Bar, *};`.
+    pub dropped: Vec<String>,
+}
+
+impl FormatStats {
+    /// The number of statements eliminated by merging.
+    pub fn merged(&self) -> usize {
+        self.parsed.saturating_sub(self.emitted)
+    }
+}
+
+impl fmt::Display for FormatStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} use statements parsed, {} emitted ({} merged)",
+            self.parsed,
+            self.emitted,
+            self.merged()
+        )?;
+        let mut custom = self
+            .categories
+            .keys()
+            .filter(|category| !Category::ALL.contains(category))
+            .collect::<Vec<_>>();
+        custom.sort_by_key(|category| category.as_str());
+
+        for (i, category) in Category::ALL.iter().chain(custom).enumerate() {
+            let count = self.categories.get(category).copied().unwrap_or_default();
+            write!(f, "{}{category}: {count}", if i == 0 { "; " } else { ", " })?;
+        }
+        Ok(())
+    }
+}
@@ -1,25 +1,31 @@
 mod walk;
 
+use std::cmp::Ordering;
+
 use syn::{token::Brace, Ident, Token, UseGlob, UseGroup, UseName, UsePath, UseRename, UseTree};
 
+pub(crate) use self::walk::split_root_group;
 use self::walk::walk_use_tree;
-use crate::map::Name;
+use crate::{map::Name, SortMode};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(super) enum Node {
-    Ident { ident: Ident, children: Vec<Node> },
+    /// An imported name, e.g. `b` in `a::b`.
+    Name(Ident),
+    /// A path segment with the imports under it, e.g. `a` in `a::b`. Only ever left without
+    /// children while the tree is edited, and pruned before it's converted back.
+    Parent {
+        ident: Ident,
+        children: Vec<Node>,
+    },
     Glob,
-    Rename { ident: Ident, rename: Ident },
+    Rename {
+        ident: Ident,
+        rename: Ident,
+    },
 }
 
 impl Node {
-    fn ident(ident: Ident) -> Self {
-        Self::Ident {
-            ident,
-            children: Vec::new(),
-        }
-    }
-
     fn glob() -> Self {
         Self::Glob
     }
@@ -27,25 +33,313 @@ impl Node {
     fn rename(ident: Ident, rename: Ident) -> Self {
         Self::Rename { ident, rename }
     }
+
+    fn self_leaf() -> Self {
+        Self::Name(Ident::from(<Token![self]>::default()))
+    }
+
+    fn is_self_leaf(&self) -> bool {
+        matches!(self, Self::Name(ident) if ident == "self")
+    }
+
+    fn name(&self) -> Name {
+        match self {
+            Self::Name(ident) | Self::Parent { ident, .. } => Name::Ident(ident.clone()),
+            Self::Glob => Name::Glob,
+            Self::Rename { ident, rename } => Name::Rename {
+                ident: ident.clone(),
+                rename: rename.clone(),
+            },
+        }
+    }
+}
+
+impl Node {
+    /// The rank of the node's kind in the order of group members (see [`Node::cmp_with`]).
+    fn rank(&self) -> u8 {
+        match self {
+            _ if self.is_self_leaf() => 0,
+            Self::Name(_) => 1,
+            Self::Rename { .. } => 2,
+            Self::Parent { .. } => 3,
+            Self::Glob => 4,
+        }
+    }
+
+    /// The built-in order of group members: `self`, then named imports, renames, parents (each
+    /// sorted by ident, and renames then by alias, or the other way around if `by_alias` is set,
+    /// see [`Name::cmp_with`]), and finally the glob, e.g.
+    /// `foo::{self, Bar, Baz as B, bar::{..}, *}`. Parents are only compared by ident, since a
+    /// group never has two parents with the same one.
+    fn cmp_with(&self, other: &Self, by_alias: bool, mode: SortMode) -> Ordering {
+        self.rank()
+            .cmp(&other.rank())
+            .then_with(|| self.name().cmp_with(&other.name(), by_alias, mode))
+    }
+}
+
+/// Sorts `nodes`, and the children of each of them, by `cmp`.
+fn sort_by(nodes: &mut [Node], cmp: &impl Fn(&Node, &Node) -> Ordering) {
+    nodes.sort_by(cmp);
+
+    for node in nodes {
+        if let Node::Parent { children, .. } = node {
+            sort_by(children, cmp);
+        }
+    }
 }
 
 impl From<Name> for Node {
     fn from(value: Name) -> Self {
         match value {
-            Name::Ident(ident) => Self::ident(ident),
+            Name::Ident(ident) => Self::Name(ident),
             Name::Glob => Self::glob(),
             Name::Rename { ident, rename } => Self::rename(ident, rename),
         }
     }
 }
 
-#[derive(Default)]
-struct Visitor {
+fn find_child_by_ident(children: &[Node], ident: &Ident) -> Option<usize> {
+    children.iter().position(|node| {
+        matches!(node, Node::Name(other) | Node::Parent { ident: other, .. } if other == ident)
+    })
+}
+
+/// Finds (or creates) the child node for `segment`, and returns its children.
+///
+/// If `segment` was previously imported as a leaf (e.g. `use std::io;`), it is promoted to a
+/// parent with a `self` child, so that both imports are kept.
+fn descend_path_segment<'a>(children: &'a mut Vec<Node>, segment: &Ident) -> &'a mut Vec<Node> {
+    let index = match find_child_by_ident(children, segment) {
+        Some(index) => {
+            if let Node::Name(ident) = &children[index] {
+                children[index] = Node::Parent {
+                    ident: ident.clone(),
+                    children: vec![Node::self_leaf()],
+                };
+            }
+            index
+        }
+        None => {
+            children.push(Node::Parent {
+                ident: segment.clone(),
+                children: Vec::new(),
+            });
+            children.len() - 1
+        }
+    };
+
+    let Node::Parent { children, .. } = &mut children[index] else {
+        unreachable!()
+    };
+    children
+}
+
+fn insert_leaf(children: &mut Vec<Node>, leaf: Node) {
+    if let Node::Name(ident) = &leaf {
+        if let Some(index) = find_child_by_ident(children, ident) {
+            // importing a module that's already a parent, e.g. `use std::io;` after
+            // `use std::io::Read;`
+            if let Node::Parent { children, .. } = &mut children[index] {
+                if !children.iter().any(Node::is_self_leaf) {
+                    children.insert(0, Node::self_leaf());
+                }
+            }
+            return;
+        }
+    }
+
+    if !children.contains(&leaf) {
+        children.push(leaf);
+    }
+}
+
+/// A forest of merged use trees, one per distinct root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Tree(Vec<Node>);
+
+impl Node {
+    fn drop_glob_covered(&mut self, prefix: &str, dropped: &mut Vec<String>) {
+        let Self::Parent { ident, children } = self else {
+            return;
+        };
+        let prefix = format!("{prefix}{ident}::");
+
+        if children.contains(&Node::Glob) {
+            // `self` isn't imported by the glob, and renames import under a different name
+            children.retain(|child| match child {
+                Node::Name(ident) if !child.is_self_leaf() => {
+                    dropped.push(format!("{prefix}{ident}"));
+                    false
+                }
+                _ => true,
+            });
+        }
+
+        for child in children {
+            child.drop_glob_covered(&prefix, dropped);
+        }
+    }
+}
+
+/// Removes the parents under `children` that are left without children (e.g. after their
+/// members were taken out of the tree), which would otherwise be emitted as empty groups.
+fn prune(children: &mut Vec<Node>) {
+    children.retain_mut(|child| match child {
+        Node::Parent { children, .. } => {
+            prune(children);
+            !children.is_empty()
+        }
+        _ => true,
+    });
+}
+
+/// Prefixes `tree` with the path segment `ident`.
+fn prefixed(ident: &Ident, tree: UseTree) -> UseTree {
+    UseTree::Path(UsePath {
+        ident: ident.clone(),
+        colon2_token: <Token![::]>::default(),
+        tree: Box::new(tree),
+    })
+}
+
+/// Removes the renames nested under `children`, returning the paths to them. This can leave
+/// parents without children, to be pruned.
+fn take_renames(children: &mut Vec<Node>) -> Vec<UseTree> {
+    let mut renames = Vec::new();
+
+    children.retain_mut(|child| match child {
+        Node::Rename { .. } => {
+            renames.push(UseTree::from(child.clone()));
+            false
+        }
+        Node::Parent { ident, children } => {
+            renames.extend(
+                take_renames(children)
+                    .into_iter()
+                    .map(|tree| prefixed(ident, tree)),
+            );
+            true
+        }
+        _ => true,
+    });
+
+    renames
+}
+
+/// Removes the `self` imports under `children`, returning the paths to the modules they import
+/// (only for modules that still import other members, the rest turning back into leaves).
+fn take_self_imports(children: &mut [Node]) -> Vec<UseTree> {
+    let mut modules = Vec::new();
+
+    for child in children {
+        let Node::Parent { ident, children } = child else {
+            continue;
+        };
+
+        modules.extend(
+            take_self_imports(children)
+                .into_iter()
+                .map(|tree| prefixed(ident, tree)),
+        );
+        if let Some(index) = children.iter().position(Node::is_self_leaf) {
+            children.remove(index);
+            if children.is_empty() {
+                *child = Node::Name(ident.clone());
+            } else {
+                modules.push(UseTree::Name(UseName {
+                    ident: ident.clone(),
+                }));
+            }
+        }
+    }
+
+    modules
+}
+
+impl Tree {
+    /// Removes `self` imports from groups (e.g. in `foo::{self, bar}`), returning a standalone
+    /// tree for each module they import (e.g. `foo`).
+    pub(super) fn take_self_imports(&mut self) -> Vec<UseTree> {
+        take_self_imports(&mut self.0)
+    }
+
+    /// Removes renames nested in groups (e.g. `Bar as Baz` in `foo::{Bar as Baz, Qux}`),
+    /// returning a standalone tree for each of them (e.g. `foo::Bar as Baz`).
+    pub(super) fn take_renames(&mut self) -> Vec<UseTree> {
+        take_renames(&mut self.0)
+    }
+
+    /// Sorts the nodes of the tree at every level in the built-in order (see
+    /// [`Node::cmp_with`]), so that the output doesn't depend on the order of the input.
+    pub(super) fn sort(&mut self, by_alias: bool, mode: SortMode) {
+        sort_by(&mut self.0, &|node: &Node, other: &Node| {
+            node.cmp_with(other, by_alias, mode)
+        });
+    }
+
+    /// Sorts the nodes of the tree at every level by a custom order, rather than the built-in one,
+    /// which only breaks ties (so that e.g. `self` still comes first among equal names).
+    pub(super) fn sort_by(
+        &mut self,
+        cmp: impl Fn(&Name, &Name) -> Ordering,
+        by_alias: bool,
+        mode: SortMode,
+    ) {
+        sort_by(&mut self.0, &|node: &Node, other: &Node| {
+            cmp(&node.name(), &other.name()).then_with(|| node.cmp_with(other, by_alias, mode))
+        });
+    }
+
+    /// Drops named imports that are already imported by a glob sibling, e.g. `Bar` in
+    /// `foo::{Bar, *}`, returning their paths (e.g. `foo::Bar`).
+    pub(super) fn drop_glob_covered(&mut self) -> Vec<String> {
+        let mut dropped = Vec::new();
+        for node in &mut self.0 {
+            node.drop_glob_covered("", &mut dropped);
+        }
+        dropped
+    }
+
+    /// Replaces groups of nothing but `self` with imports of their module, e.g. `foo::{self}`
+    /// with `foo`.
+    pub(super) fn drop_lone_self(&mut self) {
+        drop_lone_self(&mut self.0);
+    }
+
+    /// Converts the tree into a standalone path per import (see [`into_paths`]), pruning
+    /// childless parents first.
+    pub(super) fn into_paths(mut self) -> Vec<UseTree> {
+        prune(&mut self.0);
+        into_paths(self.0)
+    }
+
+    /// Converts the tree back into use trees, one per root, pruning childless parents first.
+    pub(super) fn into_use_trees(mut self) -> impl Iterator<Item = UseTree> {
+        prune(&mut self.0);
+        self.0.into_iter().map(UseTree::from)
+    }
+}
+
+fn drop_lone_self(nodes: &mut [Node]) {
+    for node in nodes {
+        if let Node::Parent { ident, children } = node {
+            drop_lone_self(children);
+            if let [child] = children.as_slice() {
+                if child.is_self_leaf() {
+                    *node = Node::Name(ident.clone());
+                }
+            }
+        }
+    }
+}
+
+struct Visitor<'a> {
     current_path: Vec<Ident>,
-    root_node: Option<Node>,
+    roots: &'a mut Vec<Node>,
 }
 
-impl walk::Visitor for Visitor {
+impl walk::Visitor for Visitor<'_> {
     fn enter_path(&mut self, ident: Ident) {
         self.current_path.push(ident);
     }
@@ -55,63 +349,121 @@ impl walk::Visitor for Visitor {
     }
 
     fn visit_name(&mut self, name: Name) {
-        let mut path_segments = self.current_path.iter();
-        let mut node = match (&mut self.root_node, path_segments.next()) {
-            // handle tree with just one leaf, e.g. `use std;`
-            (None, None) => {
-                self.root_node = Some(Node::from(name));
-                return;
-            }
-            (Some(root), Some(first_segment)) => {
-                assert!(
-                    matches!(root, Node::Ident { ident,.. } if *ident == *first_segment),
-                    "trying to visit tree with different root node"
-                );
-                root
-            }
-            (None, Some(first_segment)) => {
-                self.root_node.insert(Node::ident(first_segment.clone()))
-            }
+        let mut children = &mut *self.roots;
+        for segment in &self.current_path {
+            children = descend_path_segment(children, segment);
+        }
 
-            (Some(_), None) => unreachable!(),
-        };
+        insert_leaf(children, Node::from(name));
+    }
+}
 
-        for path in path_segments {
-            node =
-                {
-                    let Node::Ident { children, .. } = node else {
-                        unreachable!()
-                    };
-                    if let Some((existing_node, _)) = children.iter().enumerate().find(
-                        |(_, node)| matches!(node, Node::Ident { ident, ..} if *ident == *path),
-                    ) {
-                        &mut children[existing_node]
-                    } else {
-                        children.push(Node::ident(path.clone()));
-                        children.last_mut().unwrap()
-                    }
-                };
-        }
+/// Collects the paths to each of the imports of a tree, as text.
+#[derive(Default)]
+struct PathVisitor {
+    current_path: Vec<Ident>,
+    imports: Vec<(Vec<Ident>, Name)>,
+}
 
-        let Node::Ident { children, .. } = node else {
-            unreachable!();
-        };
+impl walk::Visitor for PathVisitor {
+    fn enter_path(&mut self, ident: Ident) {
+        self.current_path.push(ident);
+    }
+
+    fn leave_path(&mut self) {
+        self.current_path.pop().unwrap();
+    }
 
-        children.push(Node::from(name));
+    fn visit_name(&mut self, name: Name) {
+        self.imports.push((self.current_path.clone(), name));
     }
 }
 
-impl FromIterator<UseTree> for Node {
-    fn from_iter<T: IntoIterator<Item = UseTree>>(iter: T) -> Self {
-        let mut visitor = Visitor::default();
+/// Flattens `tree` into each of its imports, with the path leading to it.
+fn imports(tree: UseTree) -> Vec<(Vec<Ident>, Name)> {
+    let mut visitor = PathVisitor::default();
+    walk_use_tree(tree, &mut visitor);
+    visitor.imports
+}
+
+fn join(path: impl IntoIterator<Item = String>) -> String {
+    path.into_iter().collect::<Vec<_>>().join("::")
+}
+
+/// The number of segments of the shallowest import in `tree`, e.g. 2 for `a::{b, c::d}`. A `self`
+/// import is as deep as its module.
+pub(crate) fn depth(tree: &UseTree) -> usize {
+    match tree {
+        UseTree::Path(UsePath { tree, .. }) => 1 + depth(tree),
+        UseTree::Name(UseName { ident }) if ident == "self" => 0,
+        UseTree::Name(_) | UseTree::Rename(_) | UseTree::Glob(_) => 1,
+        UseTree::Group(UseGroup { items, .. }) => items.iter().map(depth).min().unwrap_or(0),
+    }
+}
+
+/// Flattens `tree` into the paths to each of its imports, e.g. `a::b` and `a::c as d` for
+/// `a::{b, c as d}`.
+pub(crate) fn paths(tree: UseTree) -> Vec<String> {
+    imports(tree)
+        .into_iter()
+        .map(|(path, name)| {
+            let leaf = match name {
+                Name::Ident(ident) => ident.to_string(),
+                Name::Glob => "*".to_owned(),
+                Name::Rename { ident, rename } => format!("{ident} as {rename}"),
+            };
+            join(path.iter().map(Ident::to_string).chain([leaf]))
+        })
+        .collect()
+}
+
+/// The names `tree` brings into scope, each with the path of the item it names, e.g. `b` for
+/// `a::b` and `d` for `a::c` in `a::{b, c as d}`, and `a` for `a` in `a::{self}`. Globs and `_`
+/// renames don't bind names.
+pub(crate) fn bindings(tree: UseTree) -> Vec<(String, String)> {
+    imports(tree)
+        .into_iter()
+        .filter_map(|(mut path, name)| {
+            let (ident, name) = match name {
+                Name::Glob => return None,
+                Name::Rename { rename, .. } if rename == "_" => return None,
+                Name::Ident(ident) => (ident.clone(), ident),
+                Name::Rename { ident, rename } => (ident, rename),
+            };
+            if ident != "self" {
+                path.push(ident);
+            }
+            let name = if name == "self" {
+                path.last()?.to_string()
+            } else {
+                name.to_string()
+            };
+            Some((join(path.iter().map(Ident::to_string)), name))
+        })
+        .collect()
+}
+
+impl Extend<UseTree> for Tree {
+    fn extend<T: IntoIterator<Item = UseTree>>(&mut self, iter: T) {
+        let mut visitor = Visitor {
+            current_path: Vec::new(),
+            roots: &mut self.0,
+        };
         for tree in iter {
             walk_use_tree(tree, &mut visitor);
         }
-        visitor.root_node.unwrap()
     }
 }
 
-impl From<UseTree> for Node {
+impl FromIterator<UseTree> for Tree {
+    fn from_iter<T: IntoIterator<Item = UseTree>>(iter: T) -> Self {
+        let mut tree = Self::default();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl From<UseTree> for Tree {
     fn from(value: UseTree) -> Self {
         Self::from_iter([value])
     }
@@ -120,20 +472,15 @@ impl From<UseTree> for Node {
 impl From<Node> for UseTree {
     fn from(node: Node) -> Self {
         match node {
-            Node::Ident { ident, children } => {
-                if children.is_empty() {
-                    Self::Name(UseName { ident })
-                } else {
-                    Self::Path(UsePath {
-                        ident,
-                        colon2_token: <Token![::]>::default(),
-                        tree: Box::new(UseTree::Group(UseGroup {
-                            brace_token: Brace::default(),
-                            items: children.into_iter().map(UseTree::from).collect(),
-                        })),
-                    })
-                }
-            }
+            Node::Name(ident) => Self::Name(UseName { ident }),
+            Node::Parent { ident, children } => Self::Path(UsePath {
+                ident,
+                colon2_token: <Token![::]>::default(),
+                tree: Box::new(UseTree::Group(UseGroup {
+                    brace_token: Brace::default(),
+                    items: children.into_iter().map(UseTree::from).collect(),
+                })),
+            }),
             Node::Glob => Self::Glob(UseGlob {
                 star_token: <Token![*]>::default(),
             }),
@@ -146,19 +493,71 @@ impl From<Node> for UseTree {
     }
 }
 
+/// Splits `children` into a standalone path to each of the imports under them, e.g. `a::b` and
+/// `a::c` for `a::{b, c}`. `self` imports are kept in a group (`a::{self}`), since they can't end
+/// a path.
+fn into_paths(children: Vec<Node>) -> Vec<UseTree> {
+    children
+        .into_iter()
+        .flat_map(|child| match child {
+            Node::Parent { ident, children } => into_paths(children)
+                .into_iter()
+                .map(|tree| match tree {
+                    UseTree::Name(name) if name.ident == "self" => prefixed(
+                        &ident,
+                        UseTree::Group(UseGroup {
+                            brace_token: Brace::default(),
+                            items: [UseTree::Name(name)].into_iter().collect(),
+                        }),
+                    ),
+                    tree => prefixed(&ident, tree),
+                })
+                .collect(),
+            child => vec![UseTree::from(child)],
+        })
+        .collect()
+}
+
+/// Replaces groups of a single member with the member itself, e.g. `a::{b::{c}}` with `a::b::c`.
+/// Groups of just `self` (`a::{self}`) are kept, since `self` is only valid in a group.
+pub(super) fn collapse_single_groups(tree: UseTree) -> UseTree {
+    match tree {
+        UseTree::Path(mut path) => {
+            *path.tree = collapse_single_groups(*path.tree);
+            UseTree::Path(path)
+        }
+        UseTree::Group(mut group)
+            if group.items.len() == 1
+                && !matches!(&group.items[0], UseTree::Name(name) if name.ident == "self") =>
+        {
+            collapse_single_groups(group.items.pop().unwrap().into_value())
+        }
+        UseTree::Group(group) => UseTree::Group(UseGroup {
+            items: group
+                .items
+                .into_iter()
+                .map(collapse_single_groups)
+                .collect(),
+            ..group
+        }),
+        tree => tree,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use proc_macro2::Span;
     use syn::parse_quote;
 
     use super::*;
+    use crate::display::AsDisplay;
 
     macro_rules! parse_node {
         {$($tt:tt)*} => {{
             let tree: UseTree = parse_quote! {
                 $($tt)*
             };
-            Node::from(tree)
+            Tree::from(tree)
         }}
     }
 
@@ -166,12 +565,18 @@ mod tests {
         Ident::new(ident, Span::call_site())
     }
 
+    macro_rules! t {
+        ($($node:expr),+) => {
+            Tree(vec![$($node),+])
+        };
+    }
+
     macro_rules! n {
         ($name:path) => {
-            Node::ident(ident(stringify!($name)))
+            Node::Name(ident(stringify!($name)))
         };
         ($name:path, [$($node:expr),+]) => {
-            Node::Ident {
+            Node::Parent {
                 ident: ident(stringify!($name)),
                 children: vec![$($node),+],
             }
@@ -180,25 +585,195 @@ mod tests {
 
     #[test]
     fn test_tree_to_node() {
-        assert_eq!(parse_node!(std::a), n!(std, [n!(a)]));
+        assert_eq!(parse_node!(std::a), t![n!(std, [n!(a)])]);
 
         assert_eq!(
             parse_node!(std::{a::b, a::c}),
-            n!(std, [n!(a, [n!(b), n!(c)])])
+            t![n!(std, [n!(a, [n!(b), n!(c)])])]
         );
 
         assert_eq!(
-            Node::from_iter([parse_quote!(std::{a, b::c}), parse_quote!(std::{b::{d, e}})]),
-            n!(std, [n!(a), n!(b, [n!(c), n!(d), n!(e)])])
+            Tree::from_iter([parse_quote!(std::{a, b::c}), parse_quote!(std::{b::{d, e}})]),
+            t![n!(std, [n!(a), n!(b, [n!(c), n!(d), n!(e)])])]
         );
     }
 
-    #[ignore] // TODO!
+    #[test]
+    fn test_empty_groups() {
+        assert_eq!(parse_node!(foo::{}), Tree::default());
+        assert_eq!(parse_node!(foo::{bar::{}, baz::{{}}}), Tree::default());
+        assert_eq!(parse_node!(foo::{bar::{}, baz}), t![n!(foo, [n!(baz)])]);
+    }
+
     #[test]
     fn test_tree_to_node_with_self() {
         assert_eq!(
             parse_node!(std::{a, a::b, a::c}),
-            n!(std, [n!(a, [n!(self), n!(b), n!(c)])])
+            t![n!(std, [n!(a, [n!(self), n!(b), n!(c)])])]
+        );
+    }
+
+    #[test]
+    fn test_merge_module_and_member() {
+        let expected = t![n!(std, [n!(io, [n!(self), n!(Read)])])];
+
+        assert_eq!(
+            Tree::from_iter([parse_quote!(std::io), parse_quote!(std::io::Read)]),
+            expected
+        );
+        assert_eq!(
+            Tree::from_iter([parse_quote!(std::io::Read), parse_quote!(std::io)]),
+            expected
+        );
+        assert_eq!(
+            Tree::from_iter([
+                parse_quote!(std::io::{self, Read}),
+                parse_quote!(std::io),
+                parse_quote!(std::io::Read),
+            ]),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_same_leaf_under_different_parents() {
+        assert_eq!(
+            Tree::from_iter([parse_quote!(a::X), parse_quote!(b::X)]),
+            t![n!(a, [n!(X)]), n!(b, [n!(X)])]
+        );
+        assert_eq!(
+            Tree::from_iter([
+                parse_quote!(a::b::X),
+                parse_quote!(a::c::X),
+                parse_quote!(a::X)
+            ]),
+            t![n!(a, [n!(b, [n!(X)]), n!(c, [n!(X)]), n!(X)])]
+        );
+    }
+
+    #[test]
+    fn test_extend_in_batches() {
+        let trees: Vec<UseTree> = vec![
+            parse_quote!(std::io::Read),
+            parse_quote!(std::{fmt, io}),
+            parse_quote!(a::b::{c, d as e}),
+            parse_quote!(a::b),
+            parse_quote!(a::*),
+            parse_quote!(std::io::{self, Write}),
+            parse_quote!(a::b::c::f),
+        ];
+        let sorted = |mut tree: Tree| {
+            tree.sort(false, SortMode::default());
+            tree
+        };
+        let expected = sorted(Tree::from_iter(trees.clone()));
+
+        for reversed in [false, true] {
+            let mut trees = trees.clone();
+            if reversed {
+                trees.reverse();
+            }
+            for batch_size in 1..=trees.len() {
+                let mut tree = Tree::default();
+                for batch in trees.chunks(batch_size) {
+                    tree.extend(batch.iter().cloned());
+                }
+                assert_eq!(sorted(tree), expected, "{reversed} {batch_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_self() {
+        assert_eq!(
+            parse_node!(a::{b, b::c}),
+            t![n!(a, [n!(b, [n!(self), n!(c)])])]
+        );
+        assert_eq!(
+            parse_node!(a::{b::c, b::c::d}),
+            t![n!(a, [n!(b, [n!(c, [n!(self), n!(d)])])])]
+        );
+        assert_eq!(
+            parse_node!(a::{b, b::c, b::c::d}),
+            t![n!(a, [n!(b, [n!(self), n!(c, [n!(self), n!(d)])])])]
+        );
+        assert_eq!(
+            parse_node!(a::{b::c::d::e, b::c, b, b::c::d}),
+            t![n!(
+                a,
+                [n!(
+                    b,
+                    [n!(self), n!(c, [n!(self), n!(d, [n!(self), n!(e)])])]
+                )]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_root_rename_is_not_merged() {
+        assert_eq!(
+            Tree::from_iter([
+                parse_quote!(foo as bar),
+                parse_quote!(foo::Thing),
+                parse_quote!(foo),
+            ]),
+            t![
+                Node::rename(ident("foo"), ident("bar")),
+                n!(foo, [n!(self), n!(Thing)])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drop_glob_covered() {
+        let mut tree = parse_node!(foo::{self, Bar, Baz as Qux, a::{b, c::*, c::d}, *});
+        assert_eq!(tree.drop_glob_covered(), ["foo::Bar", "foo::a::c::d"]);
+        assert_eq!(
+            tree,
+            t![n!(
+                foo,
+                [
+                    n!(self),
+                    Node::rename(ident("Baz"), ident("Qux")),
+                    n!(a, [n!(b), n!(c, [Node::Glob])]),
+                    Node::Glob
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_prune_childless_parents() {
+        let mut tree = parse_node!(foo::{bar::{Baz as Qux, a::{B as C}}, quux});
+        assert_eq!(tree.take_renames().len(), 2);
+        assert_eq!(
+            tree,
+            t![n!(
+                foo,
+                [
+                    Node::Parent {
+                        ident: ident("bar"),
+                        children: vec![Node::Parent {
+                            ident: ident("a"),
+                            children: Vec::new(),
+                        }],
+                    },
+                    n!(quux)
+                ]
+            )]
+        );
+        assert_eq!(
+            tree.into_use_trees().collect::<Vec<_>>(),
+            [parse_quote!(foo::{quux})]
+        );
+
+        // a module only imported as `self` turns back into a leaf, rather than being pruned
+        let mut tree = parse_node!(foo::{bar, bar::Baz as Qux});
+        tree.take_renames();
+        tree.take_self_imports();
+        assert_eq!(
+            tree.into_use_trees().collect::<Vec<_>>(),
+            [parse_quote!(foo::{bar})]
         );
     }
 
@@ -209,4 +784,46 @@ mod tests {
             parse_quote!(std::{a::{b, c}})
         )
     }
+
+    #[test]
+    fn test_into_paths() {
+        let paths = parse_node!(a::{self, b::{c, d as e, *}, f}).into_paths();
+        assert_eq!(
+            paths
+                .iter()
+                .map(|tree| tree.as_display().to_string())
+                .collect::<Vec<_>>(),
+            ["a::{self}", "a::b::c", "a::b::d as e", "a::b::*", "a::f"]
+        );
+    }
+
+    #[test]
+    fn test_drop_lone_self() {
+        let mut tree = parse_node!(foo::{self, bar::{self}, baz::{self, qux::{self}}});
+        tree.drop_lone_self();
+        assert_eq!(
+            tree,
+            t![n!(foo, [n!(self), n!(bar), n!(baz, [n!(self), n!(qux)])])]
+        );
+
+        let mut tree = parse_node!(foo::{self});
+        tree.drop_lone_self();
+        assert_eq!(tree, t![n!(foo)]);
+    }
+
+    #[test]
+    fn test_collapse_single_groups() {
+        let collapsed = |tree: UseTree| collapse_single_groups(tree).as_display().to_string();
+
+        assert_eq!(collapsed(parse_quote!(a::{b})), "a::b");
+        assert_eq!(collapsed(parse_quote!(a::{b::{c::{d}}})), "a::b::c::d");
+        assert_eq!(collapsed(parse_quote!(a::{b::{c}, d})), "a::{b::c, d}");
+        assert_eq!(collapsed(parse_quote!(a::{b::{c as d}})), "a::b::c as d");
+        assert_eq!(collapsed(parse_quote!(a::{b::{*}})), "a::b::*");
+        assert_eq!(collapsed(parse_quote!(a::{b::{self}})), "a::b::{self}");
+        assert_eq!(
+            collapsed(parse_quote!(a::{b::{self, c}})),
+            "a::b::{self, c}"
+        );
+    }
 }
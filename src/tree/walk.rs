@@ -8,23 +8,56 @@ pub(super) trait Visitor {
     fn visit_name(&mut self, name: Name);
 }
 
+enum Step {
+    Walk(UseTree),
+    LeavePath,
+}
+
+/// Walks `tree` depth-first, calling the visitor's callbacks in order. The walk uses an explicit
+/// stack rather than recursion, so it can't overflow the call stack on deep trees itself.
+///
+/// Note that the rest of the pipeline (parsing with syn, sorting, pruning and rendering trees)
+/// still recurses, so formatting very deep trees can still overflow the stack.
 pub(super) fn walk_use_tree(tree: UseTree, visitor: &mut impl Visitor) {
-    match tree {
-        UseTree::Path(UsePath { ident, tree, .. }) => {
-            visitor.enter_path(ident);
-            walk_use_tree(*tree, visitor);
-            visitor.leave_path();
-        }
-        UseTree::Name(UseName { ident }) => visitor.visit_name(Name::Ident(ident)),
-        UseTree::Rename(UseRename { ident, rename, .. }) => {
-            visitor.visit_name(Name::Rename { ident, rename })
+    let mut stack = vec![Step::Walk(tree)];
+
+    while let Some(step) = stack.pop() {
+        let tree = match step {
+            Step::Walk(tree) => tree,
+            Step::LeavePath => {
+                visitor.leave_path();
+                continue;
+            }
+        };
+
+        match tree {
+            UseTree::Path(UsePath { ident, tree, .. }) => {
+                visitor.enter_path(ident);
+                stack.push(Step::LeavePath);
+                stack.push(Step::Walk(*tree));
+            }
+            UseTree::Name(UseName { ident }) => visitor.visit_name(Name::Ident(ident)),
+            UseTree::Rename(UseRename { ident, rename, .. }) => {
+                visitor.visit_name(Name::Rename { ident, rename })
+            }
+            UseTree::Glob(_) => visitor.visit_name(Name::Glob),
+            // an empty group (e.g. in `foo::{}`) imports nothing, so nothing is visited for it
+            // (not even its path)
+            UseTree::Group(UseGroup { items, .. }) => {
+                stack.extend(items.into_iter().rev().map(Step::Walk));
+            }
         }
-        UseTree::Glob(_) => visitor.visit_name(Name::Glob),
+    }
+}
+
+/// Splits a tree rooted in a bare group (e.g. `{std::mem, crate::thing}`) into its members, so
+/// that each can be categorized on its own.
+pub(crate) fn split_root_group(tree: UseTree) -> Vec<UseTree> {
+    match tree {
         UseTree::Group(UseGroup { items, .. }) => {
-            for tree in items {
-                walk_use_tree(tree, visitor);
-            }
+            items.into_iter().flat_map(split_root_group).collect()
         }
+        tree => vec![tree],
     }
 }
 
@@ -105,4 +138,70 @@ mod tests {
             expected![[std, a], [std, b, c], [std, d, e], [std, d, f]],
         );
     }
+
+    /// Only the walk is tested on such a deep tree, the rest of the pipeline still recurses.
+    #[test]
+    fn test_walk_deep_path() {
+        #[derive(Default)]
+        struct Visitor {
+            depth: usize,
+            max_depth: usize,
+            names: usize,
+        }
+
+        impl super::Visitor for Visitor {
+            fn enter_path(&mut self, _: Ident) {
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+
+            fn leave_path(&mut self) {
+                self.depth -= 1;
+            }
+
+            fn visit_name(&mut self, _: Name) {
+                self.names += 1;
+            }
+        }
+
+        const DEPTH: usize = 10_000;
+
+        let mut tree = UseTree::Name(UseName { ident: ident("a") });
+        for _ in 0..DEPTH {
+            tree = UseTree::Path(UsePath {
+                ident: ident("a"),
+                colon2_token: Default::default(),
+                tree: Box::new(tree),
+            });
+        }
+
+        let mut visitor = Visitor::default();
+        walk_use_tree(tree, &mut visitor);
+        assert_eq!(
+            (visitor.depth, visitor.max_depth, visitor.names),
+            (0, DEPTH, 1)
+        );
+    }
+
+    #[test]
+    fn test_split_root_group() {
+        assert_eq!(
+            split_root_group(parse_use_tree! {
+                use {std::mem, {crate::thing, self::a::{b, c}}, d};
+            }),
+            [
+                parse_quote!(std::mem),
+                parse_quote!(crate::thing),
+                parse_quote!(self::a::{b, c}),
+                parse_quote!(d),
+            ]
+        );
+
+        assert_eq!(
+            split_root_group(parse_use_tree! {
+                use std::{mem, fmt};
+            }),
+            [parse_quote!(std::{mem, fmt})]
+        );
+    }
 }
@@ -16,3 +16,892 @@ fn format() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn format_comments() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    let opts = FormatOptions {
+        preserve_comments: true,
+        ..FormatOptions::default()
+    };
+    glob!("inputs/comments", "*.stdin", |path| {
+        let contents = fs::read_to_string(path).unwrap();
+        assert_snapshot!(format_str(&contents, &opts).unwrap());
+    });
+
+    Ok(())
+}
+
+#[test]
+fn format_modules() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    let opts = FormatOptions {
+        sort_module_imports: true,
+        ..FormatOptions::default()
+    };
+    glob!("inputs/modules", "*.stdin", |path| {
+        let contents = fs::read_to_string(path).unwrap();
+        assert_snapshot!(format_str(&contents, &opts).unwrap());
+    });
+
+    Ok(())
+}
+
+#[test]
+fn format_items() -> Result<(), Box<dyn Error>> {
+    use syn::Item;
+    use user_spray::{format_items, FormatOptions};
+
+    let file = syn::parse_file("use std::io::Write;\nuse crate::a;\nuse std::fmt;\n")?;
+    let items = file
+        .items
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::Use(item) => Some(item),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        format_items(items, &FormatOptions::default()),
+        "use std::{fmt, io::{Write}};\n\nuse crate::{a};"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn edits() -> Result<(), Box<dyn Error>> {
+    use user_spray::{edits, format, FormatOptions, TextEdit};
+
+    let file = "use std::fmt;\nuse std::io;\n\nfn main() {}\n\nuse crate::a;\n";
+
+    assert_eq!(
+        edits(file, &FormatOptions::default())?,
+        [
+            TextEdit {
+                range: 0..26,
+                replacement: "use std::{fmt, io};".to_owned(),
+            },
+            TextEdit {
+                range: 42..55,
+                replacement: "use crate::{a};".to_owned(),
+            },
+        ]
+    );
+
+    let mut output = vec![];
+    assert!(format(file, &mut output)?);
+    let output = String::from_utf8(output)?;
+    assert_eq!(
+        output,
+        "use std::{fmt, io};\n\nfn main() {}\n\nuse crate::{a};\n"
+    );
+
+    assert_eq!(edits(&output, &FormatOptions::default())?, []);
+    assert!(!format(&output, &mut vec![])?);
+
+    Ok(())
+}
+
+#[test]
+fn category_separators() -> Result<(), Box<dyn Error>> {
+    use user_spray::format;
+
+    let mut output = vec![];
+    format(
+        "use crate::a;\nuse std::fmt;\nuse serde::Serialize;\nfn main() {}\n",
+        &mut output,
+    )?;
+    assert_eq!(
+        String::from_utf8(output)?,
+        "use std::{fmt};\n\nuse serde::{Serialize};\n\nuse crate::{a};\n\nfn main() {}\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn trailing_newline() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // a file ending with imports always ends with exactly one newline
+    for input in [
+        "use std::mem::swap;",
+        "use std::mem::swap;\n",
+        "use std::mem::swap;\n\n\n",
+    ] {
+        assert_eq!(
+            format_str(input, &FormatOptions::default())?,
+            "use std::{mem::{swap}};\n",
+            "{input:?}"
+        );
+    }
+
+    // even when the last category's block is the last thing in the file (and some are empty)
+    assert_eq!(
+        format_str("use crate::a;\nuse std::fmt;", &FormatOptions::default())?,
+        "use std::{fmt};\n\nuse crate::{a};\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn empty_groups() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    let opts = FormatOptions {
+        sort_block_imports: true,
+        ..FormatOptions::default()
+    };
+    let cases = [
+        ("use foo::{};\n", ""),
+        ("use foo::{};\n\nfn main() {}\n", "fn main() {}\n"),
+        ("fn main() {}\n\nuse foo::{};\n", "fn main() {}\n"),
+        ("mod a;\n\nuse foo::{};\n\nmod b;\n", "mod a;\n\nmod b;\n"),
+        ("use foo::{bar::{}};\nuse foo::baz;\n", "use foo::{baz};\n"),
+        (
+            "fn main() {\n    use foo::{};\n    let x = 1;\n}\n",
+            "fn main() {\n    let x = 1;\n}\n",
+        ),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(format_str(input, &opts)?, expected, "{input:?}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn renamed_std_crate() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // a crate renamed by a top-level import is categorized along with the crate it aliases
+    assert_eq!(
+        format_str(
+            "use serde::Serialize;\nuse rust_std::mem;\nuse std as rust_std;\nuse alloc as _;\n",
+            &FormatOptions::default()
+        )?,
+        "use alloc as _;\nuse rust_std::{mem};\nuse std as rust_std;\n\nuse serde::{Serialize};\n"
+    );
+
+    // but not by one in a function, whose alias is local to it
+    assert_eq!(
+        format_str(
+            "use rust_std::mem;\nuse std::fmt;\nfn f() { use std as rust_std; }\n",
+            &FormatOptions::default()
+        )?,
+        "use std::{fmt};\n\nuse rust_std::{mem};\n\nfn f() { use std as rust_std; }\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn root_trees() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatError, FormatOptions};
+
+    // paths, names and renames are categorized by their first ident, globs as crate-local, the
+    // members of root groups each on their own, and empty groups are dropped
+    assert_eq!(
+        format_str(
+            "use serde;\nuse crate::a;\nuse {std::fmt, {}, self::b};\nuse {};\nuse core as c;\nuse {*};\n",
+            &FormatOptions::default()
+        )?,
+        "use core as c;\nuse std::{fmt};\n\nuse serde;\n\nuse crate::{a};\nuse self::{b};\nuse *;\n"
+    );
+
+    for input in ["use ::*;", "use ::{std::fmt, *};"] {
+        let err = format_str(input, &FormatOptions::default()).unwrap_err();
+        assert!(matches!(err, FormatError::RootGlob { .. }), "{input:?}");
+    }
+    assert_eq!(
+        format_str(
+            "use std::mem;\nuse ::{std::fmt, *};",
+            &FormatOptions::default()
+        )
+        .unwrap_err()
+        .to_string(),
+        "line 2: can't glob-import all crates"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn format_str() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    assert_eq!(
+        format_str(
+            "//! docs\nuse std::io;\nuse std::fmt;\n",
+            &FormatOptions::default()
+        )?,
+        "//! docs\nuse std::{fmt, io};\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn stats() -> Result<(), Box<dyn Error>> {
+    use user_spray::{stats, Category, FormatOptions};
+
+    let stats = stats(
+        "use std::fmt;\nuse std::io;\nuse serde::Serialize;\nfn main() {}\nuse std::mem;\n",
+        &FormatOptions::default(),
+    )?;
+    assert_eq!((stats.parsed, stats.emitted, stats.merged()), (4, 3, 1));
+    assert_eq!(stats.categories[&Category::Std], 2);
+    assert_eq!(
+        stats.to_string(),
+        "4 use statements parsed, 3 emitted (1 merged); std: 2, external: 1, crate: 0"
+    );
+
+    // a single entry can be rendered as several statements, each counted in its category
+    let stats = user_spray::stats(
+        "use std::io::{self, Read as R};
+use crate::a;
+",
+        &FormatOptions {
+            standalone_renames: true,
+            collapse_self: false,
+            ..FormatOptions::default()
+        },
+    )?;
+    assert_eq!(
+        stats.to_string(),
+        "2 use statements parsed, 3 emitted (0 merged); std: 2, external: 0, crate: 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn shuffled_input() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    let mut statements = vec![
+        // merged into one statement, whose members are sorted
+        "use std::io;",
+        "use std::mem;",
+        "use std::fmt::Display;",
+        // every other statement has a distinct key, so none of them are merged
+        "use a;",
+        "pub use a;",
+        "pub(crate) use a;",
+        "#[cfg(test)] use a;",
+        "#[cfg(not(test))] use a;",
+        "use a as b;",
+        "use a as c;",
+        "use ::a;",
+        "use b::*;",
+        "pub use std::io;",
+        "use crate::c;",
+        "use self::d;",
+    ];
+    let opts = FormatOptions {
+        always_single_line: true,
+        ..FormatOptions::default()
+    };
+    let format = |statements: &[&str]| format_str(&statements.join("\n"), &opts);
+    let expected = format(&statements)?;
+    assert!(expected.contains("use std::{io, mem, fmt::{Display}};\n"));
+
+    // a simple LCG, so the shuffles are reproducible
+    let mut state = 0x2545_f491_u32;
+    for _ in 0..20 {
+        for i in (1..statements.len()).rev() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            statements.swap(i, state as usize % (i + 1));
+        }
+        assert_eq!(format(&statements)?, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn non_import_lines_untouched() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // trailing whitespace, tabs and odd indentation outside of imports are kept as they are
+    let input = "//! docs  \n\n#![allow(unused)]\t\n\nuse std::io;\nuse std::fmt;\n\n\n\nfn main() {\t\n   let x  =  1;   \n    use crate::b;\n\tuse std::mem;\n\t\tlet _y = x;\n}  \n\nmod a {\n  use std::io;\n  use crate::a;\n\t// tabbed\n  const A: u8 = 1 ;\n}\nuse crate::c;\n   \n";
+    let opts = FormatOptions {
+        sort_block_imports: true,
+        sort_module_imports: true,
+        ..FormatOptions::default()
+    };
+    let output = format_str(input, &opts)?;
+
+    // blank lines aside (which are normalized around runs), only import lines change
+    let other_lines = |text: &str| {
+        text.lines()
+            .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with("use "))
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(other_lines(&output), other_lines(input));
+    assert_eq!(
+        output,
+        "//! docs  \n\n#![allow(unused)]\t\n\nuse std::{fmt, io};\n\n\n\nfn main() {\t\n   let x  =  1;   \n    use std::{mem};\n\n    use crate::{b};\n\t\tlet _y = x;\n}  \n\nmod a {\n  use std::{io};\n\n  use crate::{a};\n\t// tabbed\n  const A: u8 = 1 ;\n}\nuse crate::{c};\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn lossy_constructs_untouched() -> Result<(), Box<dyn Error>> {
+    use user_spray::{edits, format_str, FormatOptions};
+
+    // `use` in strings, comments and macros isn't an import, and none of this would survive being
+    // printed back from syn's tokens byte for byte
+    let rest = r##"/// Import with `use b::a;
+/// use a::b;`
+#[doc = "use z::y;"]
+pub   fn   f( )->&'static str {
+    let _ = "use b::a;\nuse a::b;";
+    let r#use = r#"use b::a;
+use a::b;"#;
+    // use b::a;
+    /* use a::b; */
+    m! { use b::a; use a::b; }
+    m!(use   b::a ;);
+    r#use
+}
+
+macro_rules! m {
+    ($($tt:tt)*) => {
+        use $crate::b::a;
+        use $crate::a::b;
+    };
+}
+
+const _: () = {
+    m![use b::a;];
+};
+"##;
+    let opts = FormatOptions {
+        sort_block_imports: true,
+        sort_module_imports: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(edits(rest, &opts)?, []);
+    assert_eq!(
+        format_str(&format!("use std::io;\nuse std::fmt;\n\n{rest}"), &opts)?,
+        format!("use std::{{fmt, io}};\n\n{rest}")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn leading_colon_not_merged() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // `::foo` always names the external crate, while `foo` may name a local item
+    assert_eq!(
+        format_str(
+            "use ::foo::A;\nuse foo::B;\nuse ::foo::C;\n",
+            &FormatOptions::default()
+        )?,
+        "use foo::{B};\nuse ::foo::{A, C};\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn root_crate_and_submodule() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // a bare crate import merges with imports from the crate, whichever comes first
+    for input in [
+        "use std;\nuse std::mem;\n",
+        "use std::mem;\nuse std;\n",
+        "use std::{mem};\nuse std;\nuse std::{self};\n",
+    ] {
+        assert_eq!(
+            format_str(input, &FormatOptions::default())?,
+            "use std::{self, mem};\n",
+            "{input:?}"
+        );
+    }
+    assert_eq!(
+        format_str(
+            "use ::serde;\nuse ::serde::Serialize;\nuse std;\n",
+            &FormatOptions::default()
+        )?,
+        "use std;\n\nuse ::serde::{self, Serialize};\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn super_chains() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // repeated `super` segments merge like any other, and are categorized by the first one
+    assert_eq!(
+        format_str(
+            "use super::super::b;\nuse serde::X;\nuse super::super::a;\n",
+            &FormatOptions::default()
+        )?,
+        "use serde::{X};\n\nuse super::{super::{a, b}};\n"
+    );
+    assert_eq!(
+        format_str(
+            "use super::super::foo::Bar;\nuse super::c;\nuse super::super::a;\n",
+            &FormatOptions::default()
+        )?,
+        "use super::{c, super::{a, foo::{Bar}}};\n"
+    );
+    assert_eq!(
+        format_str(
+            "use super::super::a;\nuse super::super::b;\n",
+            &FormatOptions {
+                collapse_single_groups: true,
+                ..FormatOptions::default()
+            }
+        )?,
+        "use super::super::{a, b};\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn group_member_order() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    let members = [
+        "foo::*",
+        "foo::Baz",
+        "foo::self",
+        "foo::bar::Qux",
+        "foo::Bar as B",
+        "foo::Bar",
+        "foo::Baz as A",
+    ];
+    let by_alias = FormatOptions {
+        sort_renames_by_alias: true,
+        ..FormatOptions::default()
+    };
+    // every permutation of the members (by Heap's algorithm) formats the same
+    let mut permutation = members;
+    let mut counters = [0; 7];
+    let mut i = 0;
+    while i < members.len() {
+        if counters[i] < i {
+            permutation.swap(if i % 2 == 0 { 0 } else { counters[i] }, i);
+            counters[i] += 1;
+            i = 0;
+
+            let input = permutation
+                .map(|member| format!("use {member};\n"))
+                .concat();
+            assert_eq!(
+                format_str(&input, &FormatOptions::default())?,
+                "use foo::{self, Bar, Baz, Bar as B, Baz as A, bar::{Qux}, *};\n",
+                "{input:?}"
+            );
+            assert_eq!(
+                format_str(&input, &by_alias)?,
+                "use foo::{self, Bar, Baz, Baz as A, Bar as B, bar::{Qux}, *};\n",
+                "{input:?}"
+            );
+        } else {
+            counters[i] = 0;
+            i += 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn format_one() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_one, FormatError, FormatOptions};
+
+    let opts = FormatOptions::default();
+    assert_eq!(
+        format_one("use std::{io::Write, fmt,io::Read};\n", &opts)?,
+        "use std::{fmt, io::{Read, Write}};"
+    );
+    assert_eq!(
+        format_one(
+            "pub use a::{b::{c}};",
+            &FormatOptions {
+                collapse_single_groups: true,
+                ..FormatOptions::default()
+            }
+        )?,
+        "pub use a::b::c;"
+    );
+    assert_eq!(
+        format_one("use {crate::a, std::fmt};", &opts)?,
+        "use std::{fmt};\n\nuse crate::{a};"
+    );
+
+    for input in ["", "use a; use b;", "fn main() {}", "use a"] {
+        assert!(
+            matches!(format_one(input, &opts), Err(FormatError::Parse(_))),
+            "{input:?}"
+        );
+    }
+    assert!(matches!(
+        format_one("use ::*;", &opts),
+        Err(FormatError::RootGlob { line: 1 })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn incremental() -> Result<(), Box<dyn Error>> {
+    use syn::Item;
+    use user_spray::{format_items, FormatOptions, Formatter};
+
+    let file = syn::parse_file(
+        "use std::io::Write;\nuse crate::a;\nuse std::fmt;\npub use self::b::c;\nuse std::io;\n",
+    )?;
+    let items = file
+        .items
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::Use(item) => Some(item),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let opts = FormatOptions::default();
+    let mut formatter = Formatter::new(&opts);
+    assert_eq!(formatter.render(), "");
+    for i in 0..items.len() {
+        formatter.push(items[i].clone());
+        assert_eq!(
+            formatter.render(),
+            format_items(items[..=i].to_vec(), &opts),
+            "{i}"
+        );
+    }
+
+    let mut extended = Formatter::new(&opts);
+    extended.extend(items);
+    assert_eq!(extended.render(), formatter.render());
+
+    Ok(())
+}
+
+#[test]
+fn format_range() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_range, format_str, FormatOptions};
+
+    let file = "use std::io;
+use std::fmt;
+
+fn main() {
+    use crate::b;
+    use crate::a;
+}
+
+use crate::d;
+use crate::c;
+
+
+mod m {}
+";
+    let opts = FormatOptions {
+        sort_block_imports: true,
+        ..FormatOptions::default()
+    };
+    let start = |text: &str| file.find(text).unwrap();
+    let end = |text: &str| start(text) + text.len();
+
+    // the whole file is formatted as usual
+    assert_eq!(
+        format_range(file, 0..file.len(), &opts)?,
+        format_str(file, &opts)?
+    );
+    assert_eq!(
+        format_range(file, start("use crate::b") - 4..end("crate::a;"), &opts)?,
+        file.replace(
+            "    use crate::b;\n    use crate::a;",
+            "    use crate::{a, b};"
+        )
+    );
+    // the blank lines after the last run are outside of the range, so they're kept
+    assert_eq!(
+        format_range(file, start("use crate::d")..end("crate::c;\n"), &opts)?,
+        file.replace("use crate::d;\nuse crate::c;", "use crate::{c, d};")
+    );
+    assert_eq!(
+        format_range(file, start("use crate::d")..file.len(), &opts)?,
+        file.replace(
+            "use crate::d;\nuse crate::c;\n\n\n",
+            "use crate::{c, d};\n\n"
+        )
+    );
+
+    // runs partially within the range are left untouched
+    for range in [
+        0..start("use std::fmt"),
+        start("std::fmt")..end("crate::b;"),
+        start("use crate::d")..start("use crate::c"),
+    ] {
+        assert_eq!(format_range(file, range.clone(), &opts)?, file, "{range:?}");
+    }
+
+    // removing an empty run doesn't remove the blank lines around it outside of the range either
+    let file = "fn a() {}\n\n    use foo::{};\n\n\nfn b() {}\n";
+    let start = |text: &str| file.find(text).unwrap();
+    let end = |text: &str| start(text) + text.len();
+    assert_eq!(
+        format_range(file, start("use")..end("{};\n"), &opts)?,
+        "fn a() {}\n\n    \n\nfn b() {}\n"
+    );
+    assert_eq!(
+        format_range(file, 0..file.len(), &opts)?,
+        "fn a() {}\n\nfn b() {}\n"
+    );
+    let file = "fn a() {}\n\nuse foo::{};\n";
+    assert_eq!(
+        format_range(file, file.find("use").unwrap()..file.len(), &opts)?,
+        "fn a() {}\n\n"
+    );
+    assert_eq!(format_range(file, 0..file.len(), &opts)?, "fn a() {}\n");
+
+    Ok(())
+}
+
+#[test]
+fn attributes_between_items() -> Result<(), Box<dyn Error>> {
+    use user_spray::{edits, FormatOptions, TextEdit};
+
+    // the attribute belongs to `use a::y;`, which doesn't split the run, but isn't merged with the
+    // other items either
+    let file = "use a::x;\n#[cfg(test)]\nuse a::y;\nuse a::z;\n";
+    assert_eq!(
+        edits(file, &FormatOptions::default())?,
+        [TextEdit {
+            range: 0..file.len() - 1,
+            replacement: "use a::{x, z};\n#[cfg(test)]\nuse a::{y};".to_owned(),
+        }]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn semicolon_ends_run() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // an item's span ends with its semicolon, however far it is from the path, so no part of the
+    // item is left behind
+    let cases = [
+        (
+            "use a::b /* c */ ;\nfn main() {}\n",
+            "use a::{b};\n\nfn main() {}\n",
+        ),
+        (
+            "use std::fmt;\nuse a::b // c\n    ;\nfn main() {}\n",
+            "use std::{fmt};\n\nuse a::{b};\n\nfn main() {}\n",
+        ),
+        ("fn main() {}\nuse a::b\n;\n", "fn main() {}\nuse a::{b};\n"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            format_str(input, &FormatOptions::default())?,
+            expected,
+            "{input:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn no_use_keyword() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatError, FormatOptions};
+
+    // files without imports are returned as they are, without being parsed
+    let opts = FormatOptions::default();
+    for input in [
+        "",
+        "fn main() {\n",
+        "fn r#use() {}\nstruct Used;\nfn reuse() {}\n",
+    ] {
+        assert_eq!(format_str(input, &opts)?, input);
+    }
+
+    // anything that may be an import is parsed
+    for input in [
+        "fn main() {\n// use\n",
+        "fn main() {\nuse a;\n",
+        "use\n",
+        "x(use)",
+    ] {
+        assert!(
+            matches!(format_str(input, &opts), Err(FormatError::Parse(_))),
+            "{input:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn macro_style_trees() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    // nested and redundant groups, as macros may generate them, are merged and flattened
+    let opts = FormatOptions {
+        collapse_single_groups: true,
+        ..FormatOptions::default()
+    };
+    let cases = [
+        ("use a::{b::{c, d}, e::{f}};", "use a::{b::{c, d}, e::f};"),
+        ("use a::{{b}, {{c::{d}}}};", "use a::{b, c::d};"),
+        ("use a::{b::{c}, b::{d}, b::c};", "use a::b::{c, d};"),
+        (
+            "use a::{b::{self, c}, b::{d::{}}, b::e};",
+            "use a::b::{self, c, e};",
+        ),
+        (
+            "use {a::{b::{c::{d}}}, a::b::{c::e}};",
+            "use a::b::c::{d, e};",
+        ),
+        (
+            "use a::{b::{c::{*, d}}, b::{self}};",
+            "use a::b::{self, c::{d, *}};",
+        ),
+        (
+            "use a::{b as c, {b as d}, b};",
+            "use a::{b, b as c, b as d};",
+        ),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            format_str(input, &opts)?,
+            format!("{expected}\n"),
+            "{input:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn imported_paths() -> Result<(), Box<dyn Error>> {
+    use user_spray::imported_paths;
+
+    let file = "use std::{fmt::{self, Display}, io::*};
+use ::serde::Serialize as Ser;
+
+mod a {
+    pub use crate::b::{c, d as _};
+}
+
+fn main() {
+    use super::e;
+}
+
+use {f, g::{}};
+
+mod m {
+    fn h() {
+        use h::i;
+        fn j() {
+            use j::k;
+        }
+    }
+
+    trait L {
+        fn l() {
+            use l::m;
+        }
+    }
+}
+";
+    assert_eq!(
+        imported_paths(file)?,
+        [
+            "std::fmt::self",
+            "std::fmt::Display",
+            "std::io::*",
+            "::serde::Serialize as Ser",
+            "crate::b::c",
+            "crate::b::d as _",
+            "super::e",
+            "f",
+            "h::i",
+            "j::k",
+            "l::m",
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn shadowed_imports() -> Result<(), Box<dyn Error>> {
+    use user_spray::{shadowed_imports, Shadowing};
+
+    let file = "use a::Error;
+use std::{fmt, io::{self, Write as _}};
+use b::Error;
+use a::{Error as _, c::*};
+use crate::fmt::{self};
+
+#[cfg(unix)]
+use c::io;
+use a::Error;
+
+mod m {
+    use d::Error;
+}
+
+fn main() {
+    use e::{io, Error::{self}};
+}
+";
+    let shadowing = |name: &str, first: (&str, usize), second: (&str, usize)| Shadowing {
+        name: name.to_owned(),
+        first: (first.0.to_owned(), first.1),
+        second: (second.0.to_owned(), second.1),
+    };
+    assert_eq!(
+        shadowed_imports(file)?,
+        [
+            shadowing("Error", ("a::Error", 1), ("b::Error", 3)),
+            shadowing("fmt", ("std::fmt", 2), ("crate::fmt", 5)),
+        ]
+    );
+    assert_eq!(
+        shadowed_imports(file)?[0].to_string(),
+        "line 3: `Error` is imported from `b::Error`, but was already imported from `a::Error` \
+         (line 1)"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn same_leaf_under_different_parents() -> Result<(), Box<dyn Error>> {
+    use user_spray::{format_str, FormatOptions};
+
+    assert_eq!(
+        format_str(
+            "use b::X;\nuse a::X;\nuse a::b::X;\nuse a::c::X;\n",
+            &FormatOptions::default()
+        )?,
+        "use a::{X, b::{X}, c::{X}};\nuse b::{X};\n"
+    );
+
+    Ok(())
+}
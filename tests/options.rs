@@ -0,0 +1,1151 @@
+use std::cmp::Ordering;
+
+use user_spray::{
+    format_with, stats, Category, CategoryMatcher, CategorySet, Classifier, Comparator, CrateOrder,
+    FormatOptions, GroupImports, Indent, SortMode, UseKey,
+};
+
+fn format(input: &str, opts: &FormatOptions) -> String {
+    let mut output = vec![];
+    format_with(input, &mut output, opts).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn std_crates() {
+    let input =
+        "use sp_std::vec::Vec;\nuse serde::Serialize;\nuse core::mem;\nuse ::sp_std::fmt;\n";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use core::{mem};\n\nuse serde::{Serialize};\nuse sp_std::{vec::{Vec}};\nuse ::sp_std::{fmt};\n"
+    );
+
+    let opts = FormatOptions {
+        std_crates: ["sp_std".to_owned()].into(),
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use core::{mem};\nuse sp_std::{vec::{Vec}};\nuse ::sp_std::{fmt};\n\nuse serde::{Serialize};\n"
+    );
+}
+
+#[test]
+fn preserve_unchanged() {
+    let input = "use std::{\n    fmt,\n    io::{self, Read},\n};\n\nfn main() {}\n";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use std::{fmt, io::{self, Read}};\n\nfn main() {}\n"
+    );
+
+    let opts = FormatOptions {
+        preserve_unchanged: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(format(input, &opts), input);
+
+    let input = "use std::{\n    io,\n    fmt,\n};\nuse std::mem;\n";
+    assert_eq!(format(input, &opts), "use std::{fmt, io, mem};\n");
+}
+
+#[test]
+fn always_single_line() {
+    let input =
+        "use std::fmt;\nuse some_crate::{first_module::FirstItem, second_module::SecondItem};\n";
+    let opts = FormatOptions {
+        max_width: 60,
+        always_single_line: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use std::{fmt};\n\n#[rustfmt::skip]\nuse some_crate::{first_module::{FirstItem}, second_module::{SecondItem}};\n"
+    );
+
+    // the skip marker is regenerated, rather than duplicated or skipping the run
+    let formatted = format(input, &opts);
+    assert_eq!(format(&formatted, &opts), formatted);
+
+    // but a statement that fits is marked by the user, so its run is skipped
+    let input = "use std::fmt;\n#[rustfmt::skip]\nuse crate::b;\nuse std::io;\n";
+    assert_eq!(format(input, &opts), input);
+}
+
+#[test]
+fn drop_glob_covered() {
+    let input = "use std::io::*;\nuse std::io::{self, Read};\nuse std::io::Write as _;\n";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use std::{io::{self, Read, Write as _, *}};\n"
+    );
+
+    let opts = FormatOptions {
+        drop_glob_covered: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use std::{io::{self, Write as _, *}};\n"
+    );
+    assert_eq!(stats(input, &opts).unwrap().dropped, ["std::io::Read"]);
+    assert_eq!(
+        stats("use ::a::{b::{C, *}, D};\n", &opts).unwrap().dropped,
+        ["::a::b::C"]
+    );
+    assert!(stats(input, &FormatOptions::default())
+        .unwrap()
+        .dropped
+        .is_empty());
+}
+
+#[test]
+fn category_order() {
+    let input = "use crate::a;\nuse serde::Serialize;\nuse std::fmt;\n";
+    let opts = FormatOptions {
+        category_order: vec![Category::Crate, Category::Std],
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use crate::{a};\n\nuse std::{fmt};\n\nuse serde::{Serialize};\n"
+    );
+
+    let opts = FormatOptions {
+        category_order: vec![Category::External, Category::External, Category::Crate],
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use serde::{Serialize};\n\nuse crate::{a};\n\nuse std::{fmt};\n"
+    );
+}
+
+#[test]
+fn group_imports_one() {
+    let input = "use crate::a;\nuse serde::Serialize;\nuse std::fmt;\nuse crate::b;\n";
+    let opts = FormatOptions {
+        group_imports: GroupImports::One,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use crate::{a, b};\nuse serde::{Serialize};\nuse std::{fmt};\n"
+    );
+}
+
+#[test]
+fn group_imports_one_keeps_merge_rules() {
+    let input = "use std::io;\nuse self::a::*;\npub use serde::Serialize;\nuse std::io::Read;\nuse self::a::b;\nuse core::mem as m;\n";
+    let opts = FormatOptions {
+        group_imports: GroupImports::One,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use core::{mem as m};\nuse self::{a::{b, *}};\nuse std::{io::{self, Read}};\npub use serde::{Serialize};\n"
+    );
+}
+
+#[test]
+fn group_imports_preserve() {
+    let input = "use crate::b;\nuse std::io;\nuse crate::a;\n\nuse serde::Serialize;\n// comment\nuse std::fmt;\nuse std::io::Read;\n\nuse crate::c;\n";
+    let opts = FormatOptions {
+        group_imports: GroupImports::Preserve,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use crate::{a, b};\nuse std::{io};\n\nuse serde::{Serialize};\nuse std::{fmt, io::{Read}};\n\nuse crate::{c};\n"
+    );
+}
+
+#[test]
+fn collapse_single_groups() {
+    let input = "use std::io::Write;\nuse std::fmt;\nuse serde::de::{self};\nuse crate::a::b::c;\nuse crate::a::b::d as e;\n";
+    let opts = FormatOptions {
+        collapse_single_groups: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use std::{fmt, io::Write};\n\nuse serde::de::{self};\n\nuse crate::a::b::{c, d as e};\n"
+    );
+}
+
+#[test]
+fn merge_pub_use() {
+    let input = "pub use foo::Baz;\npub use foo::{bar::Qux, Bar};\nuse foo::Quux;\nuse foo::Corge;\npub(crate) use foo::{A, B};\n";
+    let opts = FormatOptions {
+        merge_pub_use: false,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use foo::{Corge, Quux};\npub(crate) use foo::{A, B};\npub use foo::Bar;\npub use foo::Baz;\npub use foo::bar::Qux;\n"
+    );
+}
+
+#[test]
+fn sort_mode_smart_case() {
+    let input = "use http_server::a;\nuse HTTPServer::a;\nuse HttpClient::a;\nuse HttpServer::a;\nuse http2::a;\nuse IOError::a;\nuse IoSlice::a;\nuse io::a;\n";
+    let members = "use a::{http_server, HTTPServer, HttpClient, HttpServer, Https, HTTPSProxy, Zeta, alpha, XMLHttpRequest, Xml};\n";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use HTTPServer::{a};\nuse HttpClient::{a};\nuse HttpServer::{a};\nuse IOError::{a};\nuse IoSlice::{a};\nuse http2::{a};\nuse http_server::{a};\nuse io::{a};\n"
+    );
+
+    let opts = FormatOptions {
+        sort_mode: SortMode::SmartCase,
+        ..FormatOptions::default()
+    };
+    // `http2` is a single word, `IOError` is `io` and `error`, and idents with the same words
+    // are ordered byte-wise
+    assert_eq!(
+        format(input, &opts),
+        "use HttpClient::{a};\nuse HTTPServer::{a};\nuse HttpServer::{a};\nuse http_server::{a};\nuse http2::{a};\nuse io::{a};\nuse IOError::{a};\nuse IoSlice::{a};\n"
+    );
+    assert_eq!(
+        format(members, &opts),
+        "use a::{\n    alpha,\n    HttpClient,\n    HTTPServer,\n    HttpServer,\n    http_server,\n    Https,\n    HTTPSProxy,\n    Xml,\n    XMLHttpRequest,\n    Zeta,\n};\n"
+    );
+}
+
+#[test]
+fn crate_order() {
+    let input = "use self::a;\nuse super::b;\nuse crate::c::d;\nuse self::e::f;\nuse local::g;\nuse crate::h;\nuse serde::i;\n";
+    let classifier = Classifier::new(|path| match path {
+        ["local", ..] => Category::Crate,
+        path => Category::of_path(path, &FormatOptions::default()),
+    });
+
+    let opts = FormatOptions {
+        classifier: Some(classifier.clone()),
+        ..FormatOptions::default()
+    };
+    assert_eq!(opts.crate_order, CrateOrder::Alphabetical);
+    assert_eq!(
+        format(input, &opts),
+        "use serde::{i};\n\nuse crate::{h, c::{d}};\nuse local::{g};\nuse self::{a, e::{f}};\nuse super::{b};\n"
+    );
+
+    let opts = FormatOptions {
+        crate_order: CrateOrder::KeywordsFirst,
+        classifier: Some(classifier),
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use serde::{i};\n\nuse crate::{h, c::{d}};\nuse super::{b};\nuse self::{a, e::{f}};\nuse local::{g};\n"
+    );
+
+    // keyword roots are sorted before all others, even in a single block
+    let opts = FormatOptions {
+        crate_order: CrateOrder::KeywordsFirst,
+        group_imports: GroupImports::One,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use crate::{h, c::{d}};\nuse super::{b};\nuse self::{a, e::{f}};\nuse local::{g};\nuse serde::{i};\n"
+    );
+}
+
+#[test]
+fn category_set() {
+    let input = "pub use crate::a::B;
+use tokio::io;
+use crate::test_utils::assert_output;
+use std::fmt;
+pub use serde::Serialize;
+use super::c;
+use tokio_util::codec;
+use self::d;
+";
+
+    // the default set categorizes like the default options
+    let opts = FormatOptions {
+        categories: Some(CategorySet::default()),
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        format(input, &FormatOptions::default())
+    );
+
+    let categories = CategorySet::new()
+        .with(Category::Custom("exports"), CategoryMatcher::Public)
+        .with(
+            Category::Custom("tests"),
+            CategoryMatcher::Prefix(vec!["crate".into(), "test_utils".into()]),
+        )
+        .with(Category::Std, CategoryMatcher::Builtin(Category::Std))
+        .with(
+            Category::Custom("tokio"),
+            CategoryMatcher::custom(|path| path[0].starts_with("tokio")),
+        )
+        .with(
+            Category::Crate,
+            CategoryMatcher::Roots(["super".into()].into()),
+        )
+        .without_blank_line();
+    let opts = FormatOptions {
+        categories: Some(categories),
+        ..FormatOptions::default()
+    };
+    // `self::d` isn't matched by the `Crate` category, but the built-in categorization puts it
+    // there
+    assert_eq!(
+        format(input, &opts),
+        "pub use crate::{a::{B}};
+pub use serde::{Serialize};
+
+use crate::{test_utils::{assert_output}};
+
+use std::{fmt};
+
+use tokio::{io};
+use tokio_util::{codec};
+use self::{d};
+use super::{c};
+"
+    );
+}
+
+#[test]
+fn sort_mode_by_depth_then_name() {
+    let input = "pub use std::collections::HashMap;\npub use a::b::c::D;\npub use std::mem;\npub use z::Y;\npub use a::b::E;\n";
+    let opts = |sort_mode| FormatOptions {
+        sort_mode,
+        merge_pub_use: false,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts(SortMode::Ascii)),
+        "pub use std::collections::HashMap;\npub use std::mem;\n\npub use a::b::E;\npub use a::b::c::D;\npub use z::Y;\n"
+    );
+    // statements with shallower imports come first, and `std::collections::HashMap` stays with
+    // `std::mem`, since they're rendered from the same (merged) statement
+    assert_eq!(
+        format(input, &opts(SortMode::ByDepthThenName)),
+        "pub use std::mem;\npub use std::collections::HashMap;\n\npub use z::Y;\npub use a::b::E;\npub use a::b::c::D;\n"
+    );
+
+    // merged statements are as deep as their shallowest import
+    let input = "use b::c::D;\nuse a::b::c::d::E;\nuse a::F;\nuse c::d::e::G;\n";
+    assert_eq!(
+        format(input, &opts(SortMode::ByDepthThenName)),
+        "use a::{F, b::{c::{d::{E}}}};\nuse b::{c::{D}};\nuse c::{d::{e::{G}}};\n"
+    );
+}
+
+#[test]
+fn separate_pub_use() {
+    let input = "pub use serde::Serialize;\nuse tokio::io;\npub(crate) use rand::Rng;\nuse std::fmt;\npub use tokio::runtime;\nuse serde::Deserialize;\npub use crate::a;\n";
+    let opts = FormatOptions {
+        separate_pub_use: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use std::{fmt};\n\nuse serde::{Deserialize};\nuse tokio::{io};\npub(crate) use rand::{Rng};\n\npub use serde::{Serialize};\npub use tokio::{runtime};\n\npub use crate::{a};\n"
+    );
+}
+
+#[test]
+fn blank_after_module_docs() {
+    let opts = |blank_lines| FormatOptions {
+        blank_after_module_docs: blank_lines,
+        ..FormatOptions::default()
+    };
+
+    for input in [
+        "//! docs\nuse std::fmt;\n",
+        "//! docs\n\n\n\nuse std::fmt;\n",
+        "//! docs\n\nuse std::{fmt};\n",
+    ] {
+        assert_eq!(
+            format(input, &opts(None)),
+            input.replace("std::fmt", "std::{fmt}")
+        );
+        assert_eq!(format(input, &opts(Some(0))), "//! docs\nuse std::{fmt};\n");
+        assert_eq!(
+            format(input, &opts(Some(1))),
+            "//! docs\n\nuse std::{fmt};\n"
+        );
+    }
+
+    // inner attributes count as docs, but not items preceding the imports
+    assert_eq!(
+        format(
+            "//! docs\n#![allow(unused)]\nuse std::fmt;\n",
+            &opts(Some(1))
+        ),
+        "//! docs\n#![allow(unused)]\n\nuse std::{fmt};\n"
+    );
+    assert_eq!(
+        format("//! docs\nmod a;\nuse std::fmt;\n", &opts(Some(1))),
+        "//! docs\nmod a;\nuse std::{fmt};\n"
+    );
+    assert_eq!(
+        format("use std::fmt;\n", &opts(Some(1))),
+        "use std::{fmt};\n"
+    );
+}
+
+#[test]
+fn normalize_trailing_blank_lines() {
+    let opts = |normalize| FormatOptions {
+        normalize_trailing_blank_lines: normalize,
+        ..FormatOptions::default()
+    };
+
+    for input in [
+        "use std::fmt;\nfn main() {}\n",
+        "use std::fmt;\n\nfn main() {}\n",
+        "use std::fmt;\n\n\n\nfn main() {}\n",
+    ] {
+        assert_eq!(
+            format(input, &opts(true)),
+            "use std::{fmt};\n\nfn main() {}\n"
+        );
+        assert_eq!(
+            format(input, &opts(false)),
+            input.replace("std::fmt", "std::{fmt}")
+        );
+    }
+
+    // a file ending with imports still ends with a single newline
+    assert_eq!(
+        format("use std::fmt;\n\n\n", &opts(false)),
+        "use std::{fmt};\n"
+    );
+}
+
+#[test]
+fn wrapping() {
+    let input = "use some_crate::{first_module::{FirstItem, SecondItem}, second_module::Item};\n";
+    let opts = FormatOptions {
+        max_width: 50,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use some_crate::{
+    first_module::{FirstItem, SecondItem},
+    second_module::{Item},
+};
+"
+    );
+
+    let opts = FormatOptions {
+        max_width: 30,
+        indent: Indent::Tabs,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use some_crate::{
+\tfirst_module::{
+\t\tFirstItem,
+\t\tSecondItem,
+\t},
+\tsecond_module::{Item},
+};
+"
+    );
+
+    let opts = FormatOptions {
+        max_width: 30,
+        indent: Indent::Spaces(2),
+        always_single_line: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "#[rustfmt::skip]\nuse some_crate::{first_module::{FirstItem, SecondItem}, second_module::{Item}};\n"
+    );
+}
+
+#[test]
+fn sort_block_imports() {
+    let input = "use std::io::Write;
+
+fn main() {
+    use std::fmt;
+    use crate::b;
+
+    let _ = 1;
+}
+
+impl Foo {
+    fn foo() {
+        #[cfg(test)]
+        use crate::c;
+        use std::fmt;
+    }
+}
+";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use std::{io::{Write}};
+
+fn main() {
+    use std::fmt;
+    use crate::b;
+
+    let _ = 1;
+}
+
+impl Foo {
+    fn foo() {
+        #[cfg(test)]
+        use crate::c;
+        use std::fmt;
+    }
+}
+"
+    );
+
+    let opts = FormatOptions {
+        sort_block_imports: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use std::{io::{Write}};
+
+fn main() {
+    use std::{fmt};
+
+    use crate::{b};
+
+    let _ = 1;
+}
+
+impl Foo {
+    fn foo() {
+        use std::{fmt};
+
+        #[cfg(test)]
+        use crate::{c};
+    }
+}
+"
+    );
+}
+
+#[test]
+fn sort_block_imports_nested() {
+    let input = "mod m {
+    fn f() {
+        use crate::b;
+        use std::fmt;
+
+        fn g() {
+            use crate::c;
+            use std::io;
+        }
+
+        if true {
+            use crate::d;
+            use std::mem;
+        }
+    }
+}
+";
+    let opts = FormatOptions {
+        sort_block_imports: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "mod m {
+    fn f() {
+        use std::{fmt};
+
+        use crate::{b};
+
+        fn g() {
+            use std::{io};
+
+            use crate::{c};
+        }
+
+        if true {
+            use crate::d;
+            use std::mem;
+        }
+    }
+}
+"
+    );
+}
+
+#[test]
+fn reorder_only() {
+    let input = "use crate::b;
+use std::{
+    io, // for `Write`
+    fmt,
+};
+use serde::Serialize;
+use std::io::Write;
+use {crate::a, std::mem};
+";
+    let opts = FormatOptions {
+        reorder_only: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use std::{
+    io, // for `Write`
+    fmt,
+};
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::b;
+use {crate::a, std::mem};
+"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize() {
+    let opts: FormatOptions = toml::from_str(
+        r#"
+        max_width = 80
+        indent = "tabs"
+        group_imports = "One"
+        category_order = ["crate", "std", "external"]
+        std_crates = ["sp_std"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(opts.max_width, 80);
+    assert_eq!(opts.indent, Indent::Tabs);
+    assert_eq!(opts.group_imports, GroupImports::One);
+    assert_eq!(
+        opts.category_order,
+        [Category::Crate, Category::Std, Category::External]
+    );
+    assert!(opts.std_crates.contains("sp_std"));
+    assert!(!opts.drop_glob_covered);
+
+    let error = |config: &str| {
+        toml::from_str::<FormatOptions>(config)
+            .unwrap_err()
+            .message()
+            .to_owned()
+    };
+    assert!(error("indent = 2.5").starts_with("invalid type"));
+    assert!(error("blank_lines = 0").starts_with("unknown field `blank_lines`"));
+    assert_eq!(
+        error(r#"category_order = ["std", "crate", "std"]"#),
+        "category `std` appears more than once"
+    );
+    assert_eq!(
+        error(r#"category_order = ["crate", "std"]"#),
+        "category `external` is missing"
+    );
+    assert_eq!(
+        error(r#"category_order = ["extern"]"#),
+        "unknown category `extern` (expected one of `std`, `external`, `crate`)"
+    );
+    assert!(error(r#"group_imports = "Two""#).starts_with("unknown variant `Two`"));
+}
+
+#[test]
+fn from_str() {
+    let opts: FormatOptions = "
+        # a comment
+        max_width = 80
+        category_order = crate, std, external
+
+        group_imports=One
+    "
+    .parse()
+    .unwrap();
+    assert_eq!(opts.max_width, 80);
+    assert_eq!(
+        opts.category_order,
+        [Category::Crate, Category::Std, Category::External]
+    );
+    assert_eq!(opts.group_imports, GroupImports::One);
+    assert!(!opts.drop_glob_covered);
+
+    let error = |config: &str| config.parse::<FormatOptions>().unwrap_err().to_string();
+    assert_eq!(error("max_width"), "expected `KEY=VALUE`, got `max_width`");
+    assert_eq!(
+        error("category_order = std, std, crate"),
+        "invalid value `std, std, crate` for `category_order` (expected a comma-separated list \
+         of `std`, `external` and `crate`, each exactly once)"
+    );
+}
+
+#[test]
+fn preserve_comments() {
+    let input = "use std::io;
+// formatting
+use std::fmt; // for `Display`
+use crate::a;
+    // the b module
+use crate::b;
+";
+    let opts = FormatOptions {
+        preserve_comments: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "// formatting
+// for `Display`
+use std::{fmt, io};
+
+// the b module
+use crate::{a, b};
+"
+    );
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use std::{fmt, io};\n\nuse crate::{a, b};\n"
+    );
+}
+
+#[test]
+fn skip() {
+    let input = "use std::io;
+use crate::a;
+
+fn main() {}
+
+#[rustfmt::skip]
+use crate::b;
+use std::fmt;
+
+fn foo() {}
+
+// user-spray:skip
+use crate::c;
+use std::mem;
+";
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use std::{io};
+
+use crate::{a};
+
+fn main() {}
+
+#[rustfmt::skip]
+use crate::b;
+use std::fmt;
+
+fn foo() {}
+
+// user-spray:skip
+use crate::c;
+use std::mem;
+"
+    );
+}
+
+#[test]
+fn split_external_crates() {
+    let input = "use tokio::spawn;
+use serde::Serialize;
+use std::fmt;
+use serde::de::Deserialize;
+use serde as serde_;
+use tokio::sync::Mutex;
+use crate::a;
+";
+    let opts = FormatOptions {
+        split_external_crates: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use std::{fmt};
+
+use serde::{Serialize, de::{Deserialize}};
+use serde as serde_;
+
+use tokio::{spawn, sync::{Mutex}};
+
+use crate::{a};
+"
+    );
+}
+
+#[test]
+fn infer_indent() {
+    let input = "fn main() {\n\tuse some_crate::first_module::FirstItem;\n\tuse some_crate::second_module::SecondItem;\n}\n";
+    let opts = FormatOptions {
+        max_width: 40,
+        sort_block_imports: true,
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "fn main() {
+\tuse some_crate::{
+\t\tfirst_module::{FirstItem},
+\t\tsecond_module::{SecondItem},
+\t};
+}
+"
+    );
+
+    let opts = FormatOptions {
+        indent: Indent::Spaces(2),
+        ..opts
+    };
+    assert_eq!(
+        format(input, &opts),
+        "fn main() {
+\tuse some_crate::{
+\t  first_module::{FirstItem},
+\t  second_module::{SecondItem},
+\t};
+}
+"
+    );
+}
+
+#[test]
+fn standalone_renames() {
+    let input = "use foo::Qux;
+use foo::bar::{Bar as Baz, Quux};
+use foo::Corge as Grault;
+";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use foo::{Qux, Corge as Grault, bar::{Quux, Bar as Baz}};
+"
+    );
+
+    let opts = FormatOptions {
+        standalone_renames: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use foo::{Qux, bar::{Quux}};
+use foo::Corge as Grault;
+use foo::bar::Bar as Baz;
+"
+    );
+}
+
+#[test]
+fn classifier() {
+    let input = "use crate::{a, test_utils::{mock, Fixture}};
+use serde::Serialize;
+use std::fmt;
+use crate::test_utils::assert_output;
+";
+    let classifier = Classifier::new(|path| match path {
+        ["crate", "test_utils", ..] => Category::Custom("test"),
+        path => Category::of_path(path, &FormatOptions::default()),
+    });
+    let opts = FormatOptions {
+        classifier: Some(classifier.clone()),
+        ..FormatOptions::default()
+    };
+
+    // unlisted custom categories go last
+    assert_eq!(
+        format(input, &opts),
+        "use std::{fmt};
+
+use serde::{Serialize};
+
+use crate::{a};
+
+use crate::{test_utils::{Fixture, assert_output, mock}};
+"
+    );
+
+    let opts = FormatOptions {
+        classifier: Some(classifier),
+        category_order: vec![
+            Category::Std,
+            Category::Custom("test"),
+            Category::External,
+            Category::Crate,
+        ],
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use std::{fmt};
+
+use crate::{test_utils::{Fixture, assert_output, mock}};
+
+use serde::{Serialize};
+
+use crate::{a};
+"
+    );
+}
+
+#[test]
+fn comparator() {
+    let input = "use bb::X;
+use a::{Y, Z};
+use ccc;
+use a::W as V;
+";
+    let opts = FormatOptions {
+        comparator: Some(Comparator::new(|key, other| other.name().cmp(key.name()))),
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use ccc;
+use bb::{X};
+use a::{Z, Y, W as V};
+"
+    );
+
+    // longest crate names first, delegating to the built-in order for ties
+    let opts = FormatOptions {
+        comparator: Some(Comparator::new(|key, other| {
+            let len = |key: &UseKey| {
+                key.name()
+                    .ident()
+                    .map_or(0, |ident| ident.to_string().len())
+            };
+            len(other)
+                .cmp(&len(key))
+                .then_with(|| key.cmp_with(other, &FormatOptions::default()))
+        })),
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(input, &opts),
+        "use ccc;
+use bb::{X};
+use a::{W as V, Y, Z};
+"
+    );
+
+    // members the comparator can't tell apart keep the built-in order
+    let opts = FormatOptions {
+        comparator: Some(Comparator::new(|_, _| Ordering::Equal)),
+        ..FormatOptions::default()
+    };
+
+    assert_eq!(
+        format(
+            "use foo::*;\nuse foo::bar::Qux;\nuse foo::Bar;\nuse foo::self;\n",
+            &opts
+        ),
+        "use foo::{self, Bar, bar::{Qux}, *};
+"
+    );
+}
+
+#[test]
+fn category_headers() {
+    let input = "//! docs
+
+// crate-local
+use crate::a;
+
+// std
+use std::fmt;
+// the serde trait
+use serde::Serialize;
+
+fn main() {}
+";
+
+    // by default, the comment above the run stays in place, and the others are dropped
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "//! docs
+
+// crate-local
+use std::{fmt};
+
+use serde::{Serialize};
+
+use crate::{a};
+
+fn main() {}
+"
+    );
+
+    let opts = FormatOptions {
+        category_headers: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "//! docs
+
+// std
+use std::{fmt};
+
+use serde::{Serialize};
+
+// crate-local
+use crate::{a};
+
+fn main() {}
+"
+    );
+
+    let opts = FormatOptions {
+        preserve_comments: true,
+        ..opts
+    };
+    assert_eq!(
+        format(input, &opts),
+        "//! docs
+
+// std
+use std::{fmt};
+
+// the serde trait
+use serde::{Serialize};
+
+// crate-local
+use crate::{a};
+
+fn main() {}
+"
+    );
+}
+
+#[test]
+fn collapse_self() {
+    let input = "use foo;
+use foo::bar;
+use foo::baz::{self, Qux};
+use foo::quux::{self};
+";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use foo::{self, bar, baz::{self, Qux}, quux::{self}};
+"
+    );
+
+    let opts = FormatOptions {
+        collapse_self: false,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use foo;
+use foo::baz;
+use foo::{bar, baz::{Qux}, quux};
+"
+    );
+}
+
+#[test]
+fn collapse_self_round_trip() {
+    let merged = "use foo::{self, bar, baz::{self, Qux}};\n";
+    let split = "use foo;\nuse foo::baz;\nuse foo::{bar, baz::{Qux}};\n";
+
+    let opts = FormatOptions {
+        collapse_self: false,
+        ..FormatOptions::default()
+    };
+    // existing `self` imports are split out of their groups, and merged back into them
+    assert_eq!(format(merged, &opts), split);
+    assert_eq!(format(split, &FormatOptions::default()), merged);
+    assert_eq!(format(split, &opts), split);
+    assert_eq!(format(merged, &FormatOptions::default()), merged);
+
+    let opts = FormatOptions {
+        collapse_self: false,
+        collapse_single_groups: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format("use foo::{self, bar};\n", &opts),
+        "use foo;\nuse foo::bar;\n"
+    );
+}
+
+#[test]
+fn drop_lone_self() {
+    let input = "use foo::{self};\nuse bar::{self, Baz};\nuse qux::{quux::{self}, Corge};\n";
+
+    assert_eq!(
+        format(input, &FormatOptions::default()),
+        "use bar::{self, Baz};\nuse foo::{self};\nuse qux::{Corge, quux::{self}};\n"
+    );
+
+    let opts = FormatOptions {
+        drop_lone_self: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format(input, &opts),
+        "use bar::{self, Baz};\nuse foo;\nuse qux::{Corge, quux};\n"
+    );
+}
+
+#[test]
+fn align_visibility() {
+    let input = "use foo::Bar;
+pub(crate) use baz::Qux;
+pub use std::fmt;
+use std::io;
+pub use quux::Corge;
+";
+    let opts = FormatOptions {
+        align_visibility: true,
+        ..FormatOptions::default()
+    };
+
+    // the std block only mixes `pub` and private statements
+    assert_eq!(
+        format(input, &opts),
+        "    use std::{io};
+pub use std::{fmt};
+
+           use foo::{Bar};
+pub(crate) use baz::{Qux};
+pub        use quux::{Corge};
+"
+    );
+
+    // no padding when there's a single visibility
+    assert_eq!(
+        format("use foo::Bar;\nuse baz::Qux;\n", &opts),
+        "use baz::{Qux};\nuse foo::{Bar};\n"
+    );
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+
+use proptest::prelude::*;
+use quote::quote;
+use syn::{Item, UseTree};
+use user_spray::{format_str, FormatError, FormatOptions, GroupImports};
+
+fn ident() -> impl Strategy<Value = &'static str> {
+    // a small pool, so that generated paths overlap and get merged
+    prop::sample::select(&["a", "b", "c", "std"][..])
+}
+
+fn use_tree() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        ident().prop_map(str::to_owned),
+        (ident(), ident()).prop_map(|(ident, rename)| format!("{ident} as {rename}")),
+        Just("*".to_owned()),
+    ];
+    leaf.prop_recursive(4, 32, 4, |inner| {
+        prop_oneof![
+            (ident(), inner.clone()).prop_map(|(ident, tree)| format!("{ident}::{tree}")),
+            prop::collection::vec(inner, 0..4)
+                .prop_map(|items| format!("{{{}}}", items.join(", "))),
+        ]
+    })
+}
+
+fn item_use() -> impl Strategy<Value = String> {
+    (
+        prop::sample::select(&["", "pub ", "pub(crate) "][..]),
+        // path keywords are only valid as the first segment
+        prop::sample::select(&["", "::", "crate::", "self::", "super::"][..]),
+        any::<bool>(),
+        use_tree(),
+    )
+        .prop_map(|(vis, root, cfg, tree)| {
+            let attr = if cfg { "#[cfg(test)] " } else { "" };
+            format!("{attr}{vis}use {root}{tree};")
+        })
+}
+
+/// Flattens the `use` items of `file` into the set of paths they import, each prefixed with the
+/// item's attributes and visibility.
+fn imported_paths(file: &str) -> BTreeSet<String> {
+    fn flatten(prefix: &str, tree: &UseTree, paths: &mut BTreeSet<String>) {
+        match tree {
+            UseTree::Path(path) => flatten(&format!("{prefix}{}::", path.ident), &path.tree, paths),
+            // `a::{self}` imports the same thing as `a`
+            UseTree::Name(name) if name.ident == "self" && prefix.ends_with("::") => {
+                paths.insert(prefix.trim_end_matches("::").to_owned());
+            }
+            UseTree::Name(name) => {
+                paths.insert(format!("{prefix}{}", name.ident));
+            }
+            UseTree::Rename(rename) => {
+                paths.insert(format!("{prefix}{} as {}", rename.ident, rename.rename));
+            }
+            UseTree::Glob(_) => {
+                paths.insert(format!("{prefix}*"));
+            }
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    flatten(prefix, tree, paths);
+                }
+            }
+        }
+    }
+
+    let mut paths = BTreeSet::new();
+    for item in syn::parse_file(file).unwrap().items {
+        let Item::Use(item) = item else {
+            continue;
+        };
+        let attrs = &item.attrs;
+        let vis = &item.vis;
+        let prefix = format!(
+            "{} {} use {}",
+            quote!(#(#attrs)*),
+            quote!(#vis),
+            if item.leading_colon.is_some() {
+                "::"
+            } else {
+                ""
+            },
+        );
+        flatten(&prefix, &item.tree, &mut paths);
+    }
+    paths
+}
+
+proptest! {
+    #[test]
+    fn formatting_keeps_imports(
+        items in prop::collection::vec((item_use(), any::<bool>()), 0..12),
+        group_imports in prop_oneof![
+            Just(GroupImports::StdExternalCrate),
+            Just(GroupImports::One),
+            Just(GroupImports::Preserve),
+        ],
+        max_width in 10usize..120,
+        collapse_single_groups: bool,
+    ) {
+        // items may be followed by a blank line, separating them into blocks
+        let file = items
+            .iter()
+            .map(|(item, blank)| if *blank { format!("{item}\n\n") } else { format!("{item}\n") })
+            .collect::<String>();
+        prop_assume!(syn::parse_file(&file).is_ok());
+
+        let opts = FormatOptions {
+            group_imports,
+            max_width,
+            collapse_single_groups,
+            ..FormatOptions::default()
+        };
+        let output = match format_str(&file, &opts) {
+            // `use ::*;` is rejected
+            Err(FormatError::RootGlob { .. }) => return Err(TestCaseError::reject("root glob")),
+            output => output.unwrap(),
+        };
+
+        prop_assert!(syn::parse_file(&output).is_ok(), "output doesn't parse:\n{}", output);
+        prop_assert_eq!(imported_paths(&file), imported_paths(&output), "output:\n{}", output);
+    }
+}
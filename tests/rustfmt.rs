@@ -0,0 +1,152 @@
+//! Compares the import blocks of the crate's own sources, as formatted with the default options,
+//! against rustfmt's `group_imports = "StdExternalCrate"` (with `reorder_imports = true`).
+//!
+//! The blocks are expected to import the same paths, in the same order, so the categorization
+//! and the order of statements match rustfmt's. Where the two intentionally diverge, the blocks
+//! are normalized before they're compared:
+//! - rustfmt only reorders statements, while they're merged by their roots here, so each block
+//!   is compared as its roots in order (e.g. `std`, then `syn`), each with the paths imported
+//!   under it flattened.
+//! - Members are sorted differently (see
+//!   [`FormatOptions::sort_mode`](user_spray::FormatOptions::sort_mode)), so the paths under each
+//!   root are compared as a set.
+//! - Merging a module with its members yields `a::{self, b}`, which flattens to `a::self` rather
+//!   than `a`.
+//! - rustfmt orders the `self`, `super` and `crate` roots in that order, while they're sorted
+//!   alphabetically by default here (see
+//!   [`FormatOptions::crate_order`](user_spray::FormatOptions::crate_order)), so they're
+//!   reordered as rustfmt does.
+//!
+//! The test needs a rustfmt supporting `group_imports`, so it's ignored by default, and can be
+//! run with `cargo test --test rustfmt -- --ignored`.
+
+use std::{
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use syn::{spanned::Spanned, Item};
+use user_spray::{format_str, imported_paths, FormatOptions};
+
+const RUSTFMT_CONFIG: &str = "group_imports=StdExternalCrate,reorder_imports=true";
+
+/// Runs `input` through rustfmt, returning `None` if rustfmt can't be run.
+fn rustfmt(input: &str) -> Option<String> {
+    let mut rustfmt = Command::new("rustfmt")
+        .args(["--edition", "2021", "--config", RUSTFMT_CONFIG])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    rustfmt
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .ok()?;
+    let output = rustfmt.wait_with_output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8(output.stdout).unwrap())
+}
+
+/// A block of `use` items, as its roots with the paths imported under each.
+type Block = Vec<(String, Vec<String>)>;
+
+/// The top-level blocks of `use` items in `file` (separated by blank lines or other items), each
+/// normalized (see the module docs) into its roots in order, with the sorted paths under each.
+fn import_blocks(file: &str) -> Result<Vec<Block>, Box<dyn Error>> {
+    let lines = file.lines().collect::<Vec<_>>();
+    let mut blocks = Vec::<Block>::new();
+    let mut last_line = None;
+
+    for item in syn::parse_file(file)?.items {
+        let Item::Use(item) = item else {
+            last_line = None;
+            continue;
+        };
+        let span = item.span();
+        let (start, end) = (span.start().line, span.end().line);
+        if last_line.is_none_or(|last_line| start > last_line + 1) {
+            blocks.push(Vec::new());
+        }
+        last_line = Some(end);
+
+        let block = blocks.last_mut().unwrap();
+        let text = lines[start - 1..end].join("\n");
+        for path in imported_paths(&text)? {
+            let path = path.strip_suffix("::self").unwrap_or(&path).to_owned();
+            let root = match path.strip_prefix("::") {
+                Some(rest) => format!("::{}", rest.split("::").next().unwrap()),
+                None => path.split("::").next().unwrap().to_owned(),
+            };
+            match block.last_mut() {
+                Some((last_root, paths)) if *last_root == root => paths.push(path),
+                _ => block.push((root, vec![path])),
+            }
+        }
+    }
+
+    for block in &mut blocks {
+        block.sort_by_key(|(root, _)| match root.as_str() {
+            "self" => 0,
+            "super" => 1,
+            "crate" => 2,
+            _ => 3,
+        });
+        for (_, paths) in block {
+            paths.sort();
+            paths.dedup();
+        }
+    }
+    Ok(blocks)
+}
+
+fn sources(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            sources(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a rustfmt supporting `group_imports`"]
+fn matches_rustfmt() -> Result<(), Box<dyn Error>> {
+    assert_eq!(
+        rustfmt("use crate::a;\nuse std::fmt;\n").as_deref(),
+        Some("use std::fmt;\n\nuse crate::a;\n"),
+        "rustfmt is missing, or doesn't support `group_imports`"
+    );
+
+    let mut files = Vec::new();
+    for dir in ["src", "tests", "benches"] {
+        sources(&Path::new(env!("CARGO_MANIFEST_DIR")).join(dir), &mut files)?;
+    }
+    files.sort();
+
+    for path in files {
+        let file = fs::read_to_string(&path)?;
+        let formatted = format_str(&file, &FormatOptions::default())?;
+        let expected =
+            rustfmt(&file).ok_or_else(|| format!("{}: rustfmt failed", path.display()))?;
+
+        assert_eq!(
+            import_blocks(&formatted)?,
+            import_blocks(&expected)?,
+            "{}",
+            path.display()
+        );
+    }
+
+    Ok(())
+}